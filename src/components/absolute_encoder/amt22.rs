@@ -0,0 +1,184 @@
+use embedded_hal::spi::{Operation, SpiDevice};
+
+use crate::components::rotary_encoder::{Absolute, RotaryEncoder};
+
+const AMT22_NOP: u8 = 0x00;
+const AMT22_SET_ZERO: u8 = 0x70;
+const AMT22_RESET: u8 = 0x60;
+// Required between the bytes of a command.
+const AMT22_BYTE_DELAY_NS: u32 = 3_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Amt22Resolution {
+    Bits12,
+    Bits14,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Amt22Error<SPI> {
+    Spi(SPI),
+    CheckBits,
+}
+
+// The two check bits are odd parity over the odd and the even bits of the
+// 14-bit position.
+fn check_bits_valid(response: u16) -> bool {
+    let odd = (response & 0xAAAA).count_ones() % 2 == 1;
+    let even = (response & 0x5555).count_ones() % 2 == 1;
+    odd && even
+}
+
+// CUI AMT22 absolute encoder, SPI mode 0 at up to 2 MHz.
+pub struct Amt22<SPI: SpiDevice> {
+    spi: SPI,
+    resolution: Amt22Resolution,
+}
+
+impl<SPI: SpiDevice> Amt22<SPI> {
+    pub fn new(spi: SPI, resolution: Amt22Resolution) -> Self {
+        Self { spi, resolution }
+    }
+    pub fn into_inner(self) -> SPI {
+        self.spi
+    }
+    // Stores the current position as zero in the encoder. It resets itself
+    // afterwards and doesn't answer for about 200 ms.
+    pub fn set_zero(&mut self) -> Result<(), Amt22Error<SPI::Error>> {
+        self.command(AMT22_SET_ZERO).map(|_| ())
+    }
+    pub fn reset(&mut self) -> Result<(), Amt22Error<SPI::Error>> {
+        self.command(AMT22_RESET).map(|_| ())
+    }
+    fn command(&mut self, command: u8) -> Result<u16, Amt22Error<SPI::Error>> {
+        let (mut high, mut low) = ([0], [0]);
+        self.spi
+            .transaction(&mut [
+                Operation::Transfer(&mut high, &[AMT22_NOP]),
+                Operation::DelayNs(AMT22_BYTE_DELAY_NS),
+                Operation::Transfer(&mut low, &[command]),
+            ])
+            .map_err(Amt22Error::Spi)?;
+        Ok(u16::from_be_bytes([high[0], low[0]]))
+    }
+}
+
+impl<SPI: SpiDevice> RotaryEncoder for Amt22<SPI> {
+    fn resolution(&self) -> u32 {
+        match self.resolution {
+            Amt22Resolution::Bits12 => 1 << 12,
+            Amt22Resolution::Bits14 => 1 << 14,
+        }
+    }
+}
+
+impl<SPI: SpiDevice> Absolute for Amt22<SPI> {
+    type Error = Amt22Error<SPI::Error>;
+    fn get_position(&mut self) -> Result<u32, Self::Error> {
+        let response = self.command(AMT22_NOP)?;
+        if !check_bits_valid(response) {
+            return Err(Amt22Error::CheckBits);
+        }
+        let position = response & 0x3FFF;
+        Ok(match self.resolution {
+            Amt22Resolution::Bits12 => position >> 2,
+            Amt22Resolution::Bits14 => position,
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal_mock::eh1::spi::{Mock, Transaction};
+    use std::{vec, vec::Vec};
+
+    use super::*;
+
+    // Sets K1 (bit 15) and K0 (bit 14) so both halves have odd parity.
+    fn with_check_bits(position: u16) -> u16 {
+        let mut response = position & 0x3FFF;
+        if (response & 0xAAAA).count_ones() % 2 != 1 {
+            response |= 0x8000;
+        }
+        if (response & 0x5555).count_ones() % 2 != 1 {
+            response |= 0x4000;
+        }
+        response
+    }
+
+    // A NOP byte, the delay and the command byte in a single transaction.
+    fn command(command: u8, response: u16) -> [Transaction<u8>; 5] {
+        let [high, low] = response.to_be_bytes();
+        [
+            Transaction::transaction_start(),
+            Transaction::transfer(vec![AMT22_NOP], vec![high]),
+            Transaction::delay(AMT22_BYTE_DELAY_NS),
+            Transaction::transfer(vec![command], vec![low]),
+            Transaction::transaction_end(),
+        ]
+    }
+
+    fn encoder(commands: &[[Transaction<u8>; 5]], resolution: Amt22Resolution) -> Amt22<Mock<u8>> {
+        let transactions: Vec<_> = commands.iter().flatten().cloned().collect();
+        Amt22::new(Mock::new(&transactions), resolution)
+    }
+
+    #[test]
+    fn validates_check_bits() {
+        assert!(check_bits_valid(0x3FFF));
+        assert!(check_bits_valid(0xC000));
+        assert!(!check_bits_valid(0x0000));
+        // Only K1 is wrong.
+        assert!(!check_bits_valid(0x8000 | 0x3FFF));
+        // Only K0 is wrong.
+        assert!(!check_bits_valid(0x4000 | 0x3FFF));
+        for position in [1, 2, 0x1234, 0x2AAA, 0x1555] {
+            assert!(check_bits_valid(with_check_bits(position)));
+            assert!(!check_bits_valid(with_check_bits(position) ^ 1));
+        }
+    }
+
+    #[test]
+    fn reads_14_bit_position() {
+        let response = with_check_bits(0x1234);
+        let mut encoder = encoder(&[command(AMT22_NOP, response)], Amt22Resolution::Bits14);
+        assert_eq!(encoder.resolution(), 1 << 14);
+        assert_eq!(encoder.get_position(), Ok(0x1234));
+        encoder.into_inner().done();
+    }
+
+    #[test]
+    fn shifts_12_bit_position() {
+        let mut encoder = encoder(
+            &[
+                command(AMT22_NOP, with_check_bits(0x1234)),
+                command(AMT22_NOP, 0x3FFF),
+            ],
+            Amt22Resolution::Bits12,
+        );
+        assert_eq!(encoder.resolution(), 1 << 12);
+        // The two lowest bits are zero on the 12-bit model.
+        assert_eq!(encoder.get_position(), Ok(0x048D));
+        assert_eq!(encoder.get_position(), Ok(0x0FFF));
+        encoder.into_inner().done();
+    }
+
+    #[test]
+    fn rejects_bad_check_bits() {
+        let response = with_check_bits(0x1234) ^ 0x0100;
+        let mut encoder = encoder(&[command(AMT22_NOP, response)], Amt22Resolution::Bits14);
+        assert_eq!(encoder.get_position(), Err(Amt22Error::CheckBits));
+        encoder.into_inner().done();
+    }
+
+    #[test]
+    fn sends_commands_in_second_byte() {
+        let mut encoder = encoder(
+            &[command(AMT22_SET_ZERO, 0), command(AMT22_RESET, 0)],
+            Amt22Resolution::Bits14,
+        );
+        encoder.set_zero().unwrap();
+        encoder.reset().unwrap();
+        encoder.into_inner().done();
+    }
+}
@@ -0,0 +1,151 @@
+use embedded_hal::spi::SpiDevice;
+
+use crate::components::rotary_encoder::{Absolute, RotaryEncoder};
+
+const AS5048_RESOLUTION: u32 = 1 << 14;
+const READ: u16 = 1 << 14;
+const ERROR_FLAG: u16 = 1 << 14;
+const DATA_MASK: u16 = 0x3FFF;
+
+const REG_CLEAR_ERROR_FLAG: u16 = 0x0001;
+const REG_ANGLE: u16 = 0x3FFF;
+
+#[derive(Debug, PartialEq)]
+pub enum As5048Error<SPI> {
+    Spi(SPI),
+    Parity,
+    // The previous command was invalid or failed framing or parity checks. The
+    // flag is cleared when this is returned.
+    ErrorFlag,
+}
+
+// Bit 15 of every frame makes the number of ones even.
+fn with_parity(frame: u16) -> u16 {
+    frame | ((frame.count_ones() as u16 & 1) << 15)
+}
+
+// AS5048A and AS5047 14-bit magnetic encoders. Both answer a read command in the
+// following frame, so every read takes two SPI transactions (CS must toggle
+// between frames). SPI mode 1.
+pub struct As5048<SPI: SpiDevice> {
+    spi: SPI,
+}
+
+pub type As5047<SPI> = As5048<SPI>;
+
+impl<SPI: SpiDevice> As5048<SPI> {
+    pub fn new(spi: SPI) -> Self {
+        Self { spi }
+    }
+    pub fn into_inner(self) -> SPI {
+        self.spi
+    }
+    pub fn read_register(&mut self, address: u16) -> Result<u16, As5048Error<SPI::Error>> {
+        let command = with_parity(READ | (address & DATA_MASK));
+        self.transfer(command)?;
+        // Reading the angle again doubles as a NOP that clocks out the answer.
+        let response = self.transfer(with_parity(READ | REG_ANGLE))?;
+        if response.count_ones() % 2 != 0 {
+            return Err(As5048Error::Parity);
+        }
+        if response & ERROR_FLAG != 0 {
+            self.transfer(with_parity(READ | REG_CLEAR_ERROR_FLAG))?;
+            return Err(As5048Error::ErrorFlag);
+        }
+        Ok(response & DATA_MASK)
+    }
+    fn transfer(&mut self, command: u16) -> Result<u16, As5048Error<SPI::Error>> {
+        let mut buf = command.to_be_bytes();
+        self.spi
+            .transfer_in_place(&mut buf)
+            .map_err(As5048Error::Spi)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+}
+
+impl<SPI: SpiDevice> RotaryEncoder for As5048<SPI> {
+    fn resolution(&self) -> u32 {
+        AS5048_RESOLUTION
+    }
+}
+
+impl<SPI: SpiDevice> Absolute for As5048<SPI> {
+    type Error = As5048Error<SPI::Error>;
+    fn get_position(&mut self) -> Result<u32, Self::Error> {
+        Ok(self.read_register(REG_ANGLE)?.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal_mock::eh1::spi::{Mock, Transaction};
+    use std::vec::Vec;
+
+    use super::*;
+
+    // Each frame is a transaction of its own, as CS has to toggle between them.
+    fn encoder(frames: &[(u16, u16)]) -> As5048<Mock<u8>> {
+        let transactions: Vec<_> = frames
+            .iter()
+            .flat_map(|&(command, response)| {
+                [
+                    Transaction::transaction_start(),
+                    Transaction::transfer_in_place(
+                        command.to_be_bytes().to_vec(),
+                        response.to_be_bytes().to_vec(),
+                    ),
+                    Transaction::transaction_end(),
+                ]
+            })
+            .collect();
+        As5048::new(Mock::new(&transactions))
+    }
+
+    #[test]
+    fn adds_even_parity() {
+        assert_eq!(with_parity(0x4001), 0x4001);
+        assert_eq!(with_parity(0x7FFF), 0xFFFF);
+        assert_eq!(with_parity(0x7FFD), 0x7FFD);
+        assert_eq!(with_parity(0), 0);
+    }
+
+    #[test]
+    fn reads_angle_in_second_frame() {
+        // 0x1234 has five ones, so the parity bit is set.
+        let mut encoder = encoder(&[(0xFFFF, 0xFFFF), (0xFFFF, 0x9234)]);
+        assert_eq!(encoder.get_position(), Ok(0x1234));
+        encoder.into_inner().done();
+    }
+
+    #[test]
+    fn reads_registers() {
+        let mut encoder = encoder(&[(0x7FFD, 0), (0xFFFF, 0x0180)]);
+        assert_eq!(encoder.read_register(0x3FFD), Ok(0x0180));
+        encoder.into_inner().done();
+    }
+
+    #[test]
+    fn rejects_bad_parity() {
+        // Nothing is sent after the bad frame.
+        let mut encoder = encoder(&[(0xFFFF, 0), (0xFFFF, 0x1234)]);
+        assert_eq!(encoder.get_position(), Err(As5048Error::Parity));
+        encoder.into_inner().done();
+    }
+
+    #[test]
+    fn clears_error_flag() {
+        assert_eq!(with_parity(READ | REG_CLEAR_ERROR_FLAG), 0x4001);
+        let mut encoder = encoder(&[
+            // The error flag with the parity bit set, then the clear command.
+            (0xFFFF, 0),
+            (0xFFFF, 0xC003),
+            (0x4001, 0),
+            // The next read goes through again.
+            (0xFFFF, 0),
+            (0xFFFF, 0x9234),
+        ]);
+        assert_eq!(encoder.get_position(), Err(As5048Error::ErrorFlag));
+        assert_eq!(encoder.get_position(), Ok(0x1234));
+        encoder.into_inner().done();
+    }
+}
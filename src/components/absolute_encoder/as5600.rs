@@ -0,0 +1,143 @@
+use embedded_hal::i2c::I2c;
+
+use crate::components::rotary_encoder::{Absolute, RotaryEncoder};
+
+const AS5600_ADDRESS: u8 = 0x36;
+const AS5600_RESOLUTION: u32 = 1 << 12;
+
+const REG_STATUS: u8 = 0x0B;
+const REG_RAW_ANGLE: u8 = 0x0C;
+
+const STATUS_MAGNET_TOO_STRONG: u8 = 1 << 3;
+const STATUS_MAGNET_TOO_WEAK: u8 = 1 << 4;
+const STATUS_MAGNET_DETECTED: u8 = 1 << 5;
+
+#[derive(Debug, PartialEq)]
+pub enum As5600Error<I2C> {
+    I2c(I2C),
+    NoMagnet,
+    MagnetTooStrong,
+    MagnetTooWeak,
+}
+
+// AS5600 12-bit magnetic encoder. The raw angle is read, so the ZPOS/MPOS
+// settings of the chip don't apply; use `ZeroOffset` instead.
+pub struct As5600<I2C: I2c> {
+    i2c: I2C,
+    check_magnet: bool,
+}
+
+impl<I2C: I2c> As5600<I2C> {
+    // With `check_magnet` every read first checks the magnet status, at the cost
+    // of another transaction.
+    pub fn new(i2c: I2C, check_magnet: bool) -> Self {
+        Self { i2c, check_magnet }
+    }
+    pub fn into_inner(self) -> I2C {
+        self.i2c
+    }
+    pub fn check_magnet(&mut self) -> Result<(), As5600Error<I2C::Error>> {
+        let mut status = [0];
+        self.i2c
+            .write_read(AS5600_ADDRESS, &[REG_STATUS], &mut status)
+            .map_err(As5600Error::I2c)?;
+        let [status] = status;
+        if status & STATUS_MAGNET_DETECTED == 0 {
+            Err(As5600Error::NoMagnet)
+        } else if status & STATUS_MAGNET_TOO_STRONG != 0 {
+            Err(As5600Error::MagnetTooStrong)
+        } else if status & STATUS_MAGNET_TOO_WEAK != 0 {
+            Err(As5600Error::MagnetTooWeak)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<I2C: I2c> RotaryEncoder for As5600<I2C> {
+    fn resolution(&self) -> u32 {
+        AS5600_RESOLUTION
+    }
+}
+
+impl<I2C: I2c> Absolute for As5600<I2C> {
+    type Error = As5600Error<I2C::Error>;
+    fn get_position(&mut self) -> Result<u32, Self::Error> {
+        if self.check_magnet {
+            self.check_magnet()?;
+        }
+        let mut angle = [0; 2];
+        self.i2c
+            .write_read(AS5600_ADDRESS, &[REG_RAW_ANGLE], &mut angle)
+            .map_err(As5600Error::I2c)?;
+        Ok((u16::from_be_bytes(angle) & 0x0FFF).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal::i2c::ErrorKind;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    use std::{vec, vec::Vec};
+
+    use super::*;
+
+    fn status(status: u8) -> Transaction {
+        Transaction::write_read(AS5600_ADDRESS, vec![REG_STATUS], vec![status])
+    }
+
+    fn raw_angle(angle: [u8; 2]) -> Transaction {
+        Transaction::write_read(AS5600_ADDRESS, vec![REG_RAW_ANGLE], angle.to_vec())
+    }
+
+    #[test]
+    fn reads_raw_angle() {
+        let transactions = [status(STATUS_MAGNET_DETECTED), raw_angle([0x0A, 0xBC])];
+        let mut encoder = As5600::new(Mock::new(&transactions), true);
+        assert_eq!(encoder.get_position(), Ok(0x0ABC));
+        encoder.into_inner().done();
+        // The upper four bits are unused.
+        let mut encoder = As5600::new(Mock::new(&[raw_angle([0xFF, 0xFF])]), false);
+        assert_eq!(encoder.get_position(), Ok(0x0FFF));
+        encoder.into_inner().done();
+    }
+
+    #[test]
+    fn checks_magnet_status() {
+        for (magnet, result) in [
+            (STATUS_MAGNET_DETECTED, Ok(())),
+            (0, Err(As5600Error::NoMagnet)),
+            // MH and ML only mean something with MD set.
+            (STATUS_MAGNET_TOO_STRONG, Err(As5600Error::NoMagnet)),
+            (
+                STATUS_MAGNET_DETECTED | STATUS_MAGNET_TOO_STRONG,
+                Err(As5600Error::MagnetTooStrong),
+            ),
+            (
+                STATUS_MAGNET_DETECTED | STATUS_MAGNET_TOO_WEAK,
+                Err(As5600Error::MagnetTooWeak),
+            ),
+        ] {
+            // The angle is only read with a good magnet.
+            let mut transactions: Vec<_> = vec![status(magnet), status(magnet)];
+            if result.is_ok() {
+                transactions.push(raw_angle([0, 1]));
+            }
+            let mut encoder = As5600::new(Mock::new(&transactions), true);
+            assert_eq!(encoder.check_magnet(), result);
+            assert_eq!(encoder.get_position().map(|_| ()), result);
+            encoder.into_inner().done();
+        }
+    }
+
+    #[test]
+    fn reports_bus_errors() {
+        let transactions = [raw_angle([0, 0]).with_error(ErrorKind::Other)];
+        let mut encoder = As5600::new(Mock::new(&transactions), false);
+        assert_eq!(
+            encoder.get_position(),
+            Err(As5600Error::I2c(ErrorKind::Other))
+        );
+        encoder.into_inner().done();
+    }
+}
@@ -0,0 +1,3 @@
+pub mod amt22;
+pub mod as5048;
+pub mod as5600;
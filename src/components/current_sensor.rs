@@ -0,0 +1,51 @@
+// A single ADC channel that converts on demand, as embedded-hal 1.0 has no ADC
+// trait of its own.
+pub trait AdcOneShot {
+    type Error;
+    fn read(&mut self) -> Result<u16, Self::Error>;
+}
+
+// Motor current in amperes.
+pub trait CurrentSensor {
+    type Error;
+    fn current(&mut self) -> Result<f32, Self::Error>;
+}
+
+// A shunt or hall sensor amplifier on an ADC input, or a driver's current sense
+// output. Bidirectional amplifiers sit at `zero_offset` counts with no current.
+pub struct AdcCurrentSensor<A: AdcOneShot> {
+    adc: A,
+    amps_per_count: f32,
+    zero_offset: u16,
+}
+
+impl<A: AdcOneShot> AdcCurrentSensor<A> {
+    pub fn new(adc: A, amps_per_count: f32, zero_offset: u16) -> Self {
+        Self {
+            adc,
+            amps_per_count,
+            zero_offset,
+        }
+    }
+    // Averages `samples` readings as the zero point. The motor must not be driven
+    // meanwhile.
+    pub fn calibrate_zero(&mut self, samples: u16) -> Result<(), A::Error> {
+        let mut sum = 0u32;
+        for _ in 0..samples {
+            sum += self.adc.read()? as u32;
+        }
+        self.zero_offset = (sum / samples.max(1) as u32) as u16;
+        Ok(())
+    }
+    pub fn into_inner(self) -> A {
+        self.adc
+    }
+}
+
+impl<A: AdcOneShot> CurrentSensor for AdcCurrentSensor<A> {
+    type Error = A::Error;
+    fn current(&mut self) -> Result<f32, Self::Error> {
+        let counts = self.adc.read()? as i32 - self.zero_offset as i32;
+        Ok(counts as f32 * self.amps_per_count)
+    }
+}
@@ -1,5 +1,14 @@
+pub mod absolute_encoder;
+pub mod current_sensor;
 pub mod gamepad;
+pub mod motion_profile;
 pub mod motor;
 pub mod omni;
+pub mod position_control;
+pub mod quadrature;
 pub mod rotary_encoder;
+pub mod servo;
+pub mod stepper;
 pub mod switch;
+pub mod velocity_control;
+pub mod velocity_estimator;
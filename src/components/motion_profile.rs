@@ -0,0 +1,331 @@
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+use crate::units::{Angle, AngularAcceleration, AngularVelocity};
+
+// Limits are magnitudes. The jerk limit, in radians per second cubed, is only
+// used by S-curve profiles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionLimits {
+    pub max_velocity: AngularVelocity,
+    pub max_acceleration: AngularAcceleration,
+    pub max_jerk: f32,
+}
+
+impl MotionLimits {
+    // Every limit must be positive; infinity leaves it out.
+    pub fn is_valid(&self) -> bool {
+        self.max_velocity > AngularVelocity::ZERO
+            && self.max_acceleration > AngularAcceleration::ZERO
+            && self.max_jerk > 0.
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProfileState {
+    pub position: Angle,
+    pub velocity: AngularVelocity,
+    pub acceleration: AngularAcceleration,
+}
+
+// Position, velocity and acceleration in radians, relative to the start of the
+// move and in its direction.
+#[derive(Debug, Clone, Copy, Default)]
+struct Kinematics {
+    position: f32,
+    velocity: f32,
+    acceleration: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Segment {
+    duration: f32,
+    jerk: f32,
+    start: Kinematics,
+}
+
+impl Segment {
+    fn sample(&self, t: f32) -> Kinematics {
+        let Kinematics {
+            position: p,
+            velocity: v,
+            acceleration: a,
+        } = self.start;
+        let j = self.jerk;
+        Kinematics {
+            position: p + v * t + a * t * t / 2. + j * t * t * t / 6.,
+            velocity: v + a * t + j * t * t / 2.,
+            acceleration: a + j * t,
+        }
+    }
+}
+
+const SEGMENT_MAX_COUNT: usize = 7;
+
+// micromath's approximations are a few percent off, which would make the profile
+// miss its target. Two Newton steps bring them to full f32 precision.
+fn sqrt(x: f32) -> f32 {
+    if x <= 0. {
+        return 0.;
+    }
+    let mut y = x.sqrt();
+    if y > 0. {
+        for _ in 0..2 {
+            y = (y + x / y) / 2.;
+        }
+    }
+    y
+}
+
+fn cbrt(x: f32) -> f32 {
+    if x <= 0. {
+        return 0.;
+    }
+    let mut y = x.powf(1. / 3.);
+    if y > 0. {
+        for _ in 0..2 {
+            y -= (y * y * y - x) / (3. * y * y);
+        }
+    }
+    y
+}
+
+// A rest-to-rest move, made of segments of constant jerk (S-curve) or constant
+// acceleration (trapezoidal). Sample it with the time since the move started, in
+// seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct MotionProfile {
+    start: Angle,
+    target: Angle,
+    direction: f32,
+    segments: [Segment; SEGMENT_MAX_COUNT],
+    len: usize,
+}
+
+impl MotionProfile {
+    pub fn trapezoidal(start: Angle, target: Angle, limits: &MotionLimits) -> Self {
+        let distance = (target - start).abs().radians();
+        let a = limits.max_acceleration.radians_per_second_squared();
+        let mut v = limits.max_velocity.radians_per_second();
+
+        // Triangular profile if max velocity can't be reached in time.
+        if v * v / a > distance {
+            v = sqrt(distance * a);
+        }
+        let t_acc = if a > 0. { v / a } else { 0. };
+        let t_cruise = if v > 0. {
+            (distance - v * t_acc) / v
+        } else {
+            0.
+        };
+
+        let mut profile = Self::new(start, target);
+        profile.push(t_acc, a, 0.);
+        profile.push(t_cruise, 0., 0.);
+        profile.push(t_acc, -a, 0.);
+        profile
+    }
+    pub fn s_curve(start: Angle, target: Angle, limits: &MotionLimits) -> Self {
+        let distance = (target - start).abs().radians();
+        let a_max = limits.max_acceleration.radians_per_second_squared();
+        let j = limits.max_jerk;
+
+        // Time spent ramping acceleration (t_jerk) and accelerating in total
+        // (t_acc) to reach `v` from rest.
+        let acc_times = |v: f32| {
+            if v * j < a_max * a_max {
+                let t_jerk = sqrt(v / j);
+                (t_jerk, 2. * t_jerk)
+            } else {
+                let t_jerk = a_max / j;
+                (t_jerk, t_jerk + v / a_max)
+            }
+        };
+
+        let mut v = limits.max_velocity.radians_per_second();
+        if v * acc_times(v).1 > distance {
+            // Peak velocity such that accelerating and decelerating covers the
+            // whole distance, i.e. v * t_acc(v) = distance.
+            let v_cbrt = cbrt(distance * sqrt(j) / 2.);
+            v = v_cbrt * v_cbrt;
+            if v * j >= a_max * a_max {
+                let r = a_max / j;
+                v = a_max * (sqrt(r * r + 4. * distance / a_max) - r) / 2.;
+            }
+        }
+        let (t_jerk, t_acc) = acc_times(v);
+        let a = j * t_jerk;
+        let t_const_acc = t_acc - 2. * t_jerk;
+        let t_cruise = if v > 0. {
+            ((distance - v * t_acc) / v).max(0.)
+        } else {
+            0.
+        };
+
+        let mut profile = Self::new(start, target);
+        profile.push(t_jerk, 0., j);
+        profile.push(t_const_acc, a, 0.);
+        profile.push(t_jerk, a, -j);
+        profile.push(t_cruise, 0., 0.);
+        profile.push(t_jerk, 0., -j);
+        profile.push(t_const_acc, -a, 0.);
+        profile.push(t_jerk, -a, j);
+        profile
+    }
+    fn new(start: Angle, target: Angle) -> Self {
+        Self {
+            start,
+            target,
+            direction: if target >= start { 1. } else { -1. },
+            segments: [Segment::default(); SEGMENT_MAX_COUNT],
+            len: 0,
+        }
+    }
+    // Appends a segment starting where the previous one ends, with the given
+    // initial acceleration and constant jerk.
+    fn push(&mut self, duration: f32, acceleration: f32, jerk: f32) {
+        // Also skips NaN durations from degenerate limits.
+        if duration.partial_cmp(&0.) != Some(core::cmp::Ordering::Greater) {
+            return;
+        }
+        let end = match self.len.checked_sub(1) {
+            Some(last) => self.segments[last].sample(self.segments[last].duration),
+            None => Kinematics::default(),
+        };
+        self.segments[self.len] = Segment {
+            duration,
+            jerk,
+            start: Kinematics {
+                acceleration,
+                ..end
+            },
+        };
+        self.len += 1;
+    }
+    pub fn duration(&self) -> f32 {
+        self.segments[..self.len].iter().map(|s| s.duration).sum()
+    }
+    pub fn target(&self) -> Angle {
+        self.target
+    }
+    pub fn sample(&self, t: f32) -> ProfileState {
+        let mut t = t.max(0.);
+        for segment in &self.segments[..self.len] {
+            if t <= segment.duration {
+                let state = segment.sample(t);
+                return ProfileState {
+                    position: self.start + Angle::from_radians(self.direction * state.position),
+                    velocity: AngularVelocity::from_radians_per_second(
+                        self.direction * state.velocity,
+                    ),
+                    acceleration: AngularAcceleration::from_radians_per_second_squared(
+                        self.direction * state.acceleration,
+                    ),
+                };
+            }
+            t -= segment.duration;
+        }
+        ProfileState {
+            position: self.target,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> MotionLimits {
+        MotionLimits {
+            max_velocity: AngularVelocity::from_radians_per_second(2.),
+            max_acceleration: AngularAcceleration::from_radians_per_second_squared(4.),
+            max_jerk: 40.,
+        }
+    }
+
+    fn rad(radians: f32) -> Angle {
+        Angle::from_radians(radians)
+    }
+
+    fn profiles(start: f32, target: f32) -> [MotionProfile; 2] {
+        [
+            MotionProfile::trapezoidal(rad(start), rad(target), &limits()),
+            MotionProfile::s_curve(rad(start), rad(target), &limits()),
+        ]
+    }
+
+    fn assert_close(a: f32, b: f32, tolerance: f32) {
+        assert!((a - b).abs() <= tolerance, "{a} != {b}");
+    }
+
+    #[test]
+    fn triangular_short_move() {
+        // Reaching 2 rad/s takes 0.5 rad at 4 rad/s², so 0.4 rad never cruise.
+        let profile = MotionProfile::trapezoidal(rad(0.), rad(0.4), &limits());
+        let peak = sqrt(0.4 * 4.);
+        assert_close(profile.duration(), 2. * peak / 4., 1e-5);
+        let middle = profile.sample(profile.duration() / 2.);
+        assert_close(middle.position.radians(), 0.2, 1e-5);
+        assert_close(middle.velocity.radians_per_second(), peak, 1e-5);
+        assert!(peak < 2.);
+    }
+
+    #[test]
+    fn cruises_on_long_move() {
+        let profile = MotionProfile::trapezoidal(rad(0.), rad(10.), &limits());
+        // 0.5 s accelerating, 4.5 s cruising and 0.5 s decelerating.
+        assert_close(profile.duration(), 5.5, 1e-5);
+        let state = profile.sample(2.);
+        assert_close(state.velocity.radians_per_second(), 2., 1e-5);
+        assert_close(state.acceleration.radians_per_second_squared(), 0., 1e-5);
+    }
+
+    #[test]
+    fn ends_at_target() {
+        for (start, target) in [(0., 10.), (1., 1.3), (5., -3.), (0., 0.01), (2., 2.)] {
+            for profile in profiles(start, target) {
+                let end = profile.sample(profile.duration());
+                assert_close(end.position.radians(), target, 1e-4);
+                assert_close(end.velocity.radians_per_second(), 0., 1e-3);
+                assert_eq!(
+                    profile.sample(profile.duration() + 1.).position,
+                    rad(target)
+                );
+                assert_eq!(profile.sample(-1.).position, rad(start));
+            }
+        }
+    }
+
+    #[test]
+    fn position_is_continuous_and_within_limits() {
+        const DT: f32 = 0.001;
+        let limits = limits();
+        let max_velocity = limits.max_velocity.radians_per_second();
+        let max_acceleration = limits.max_acceleration.radians_per_second_squared();
+        for (start, target) in [(0., 10.), (0., 0.4), (3., -1.), (0., 0.05)] {
+            for profile in profiles(start, target) {
+                let steps = (profile.duration() / DT) as usize + 10;
+                let mut last = profile.sample(0.).position.radians();
+                for step in 1..=steps {
+                    let state = profile.sample(step as f32 * DT);
+                    let position = state.position.radians();
+                    assert!(state.velocity.radians_per_second().abs() <= max_velocity * 1.001);
+                    assert!(
+                        state.acceleration.radians_per_second_squared().abs()
+                            <= max_acceleration * 1.001
+                    );
+                    let moved = (position - last).abs();
+                    assert!(moved <= max_velocity * DT * 1.01, "jump of {moved}");
+                    // Moves never reverse, up to f32 rounding.
+                    assert!(
+                        (position - last) * (target - start) >= -1e-4,
+                        "{start}->{target} at {}: {last} -> {position}",
+                        step as f32 * DT
+                    );
+                    last = position;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::units::Duty;
+
+use super::{duty_from_speed, finite_speed, Motor};
+
+// Locked-antiphase drivers take a single PWM signal: 50% duty holds the motor
+// still, 100% is full speed clockwise and 0% full speed counterclockwise.
+pub struct LockedAntiphaseMotor<PWM: SetDutyCycle> {
+    pwm: PWM,
+}
+
+impl<PWM: SetDutyCycle> LockedAntiphaseMotor<PWM> {
+    pub fn new(pwm: PWM) -> Self {
+        Self { pwm }
+    }
+}
+
+impl<PWM: SetDutyCycle> Motor for LockedAntiphaseMotor<PWM> {
+    type Error = PWM::Error;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        let speed = finite_speed(speed);
+        let max_duty = self.pwm.max_duty_cycle();
+        let half = max_duty / 2;
+        let offset = duty_from_speed(speed, max_duty - half);
+        if speed >= Duty::ZERO {
+            self.pwm.set_duty_cycle(half + offset)
+        } else {
+            self.pwm.set_duty_cycle(half - offset.min(half))
+        }
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.set_speed(Duty::ZERO)
+    }
+    // Without an enable pin the bridge is always driven, so coasting brakes as well.
+    // Wrap the motor in an `EnabledMotor` to get a real coast.
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.brake()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockPwm;
+
+    #[test]
+    fn holds_still_on_nan_speed() {
+        let mut motor = LockedAntiphaseMotor::new(MockPwm::new(1000));
+        motor.set_speed(Duty::new(f32::NAN)).unwrap();
+        assert_eq!(motor.pwm.duty, 500);
+    }
+
+    #[test]
+    fn maps_speed_around_half_duty() {
+        let mut motor = LockedAntiphaseMotor::new(MockPwm::new(1000));
+        for (speed, duty) in [(0., 500), (0.5, 750), (1., 1000), (-0.5, 250), (-1., 0)] {
+            motor.set_speed(Duty::new(speed)).unwrap();
+            assert_eq!(motor.pwm.duty, duty, "speed {speed}");
+        }
+    }
+
+    #[test]
+    fn reaches_both_ends_with_odd_max_duty() {
+        let mut motor = LockedAntiphaseMotor::new(MockPwm::new(1001));
+        motor.set_speed(Duty::MAX).unwrap();
+        assert_eq!(motor.pwm.duty, 1001);
+        motor.set_speed(Duty::MIN).unwrap();
+        assert_eq!(motor.pwm.duty, 0);
+    }
+
+    #[test]
+    fn brakes_and_coasts_at_half_duty() {
+        let mut motor = LockedAntiphaseMotor::new(MockPwm::new(1000));
+        motor.set_speed(Duty::MAX).unwrap();
+        motor.brake().unwrap();
+        assert_eq!(motor.pwm.duty, 500);
+        motor.set_speed(Duty::MIN).unwrap();
+        motor.coast().unwrap();
+        assert_eq!(motor.pwm.duty, 500);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::units::Duty;
+
+use super::{finite_speed, Motor};
+
+const DSHOT_THROTTLE_MIN: u16 = 48;
+const DSHOT_THROTTLE_MAX: u16 = 2047;
+const DSHOT_3D_NEUTRAL: u16 = 1047;
+const DSHOT_FRAME_BITS: usize = 16;
+
+// Special commands sent in place of a throttle value. Most of them only take
+// effect while the motor is stopped and have to be repeated several times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u16)]
+pub enum DshotCommand {
+    MotorStop = 0,
+    Beep1 = 1,
+    Beep2 = 2,
+    Beep3 = 3,
+    Beep4 = 4,
+    Beep5 = 5,
+    EscInfo = 6,
+    SpinDirection1 = 7,
+    SpinDirection2 = 8,
+    Mode3dOff = 9,
+    Mode3dOn = 10,
+    SaveSettings = 12,
+    SpinDirectionNormal = 20,
+    SpinDirectionReversed = 21,
+}
+
+// 11 bits of throttle, the telemetry request bit and a 4-bit checksum. The
+// checksum is inverted for bidirectional DShot.
+pub fn dshot_frame(value: u16, telemetry: bool, bidirectional: bool) -> u16 {
+    let data = (value.min(DSHOT_THROTTLE_MAX) << 1) | telemetry as u16;
+    let mut crc = data ^ (data >> 4) ^ (data >> 8);
+    if bidirectional {
+        crc = !crc;
+    }
+    (data << 4) | (crc & 0x0F)
+}
+
+// PWM duties for sending a frame MSB first with a timer and DMA, at a period of
+// one bit: 3/4 of it high for a one and 3/8 for a zero.
+pub fn dshot_duties(frame: u16, max_duty: u16) -> [u16; DSHOT_FRAME_BITS] {
+    let mut duties = [0; DSHOT_FRAME_BITS];
+    for (i, duty) in duties.iter_mut().enumerate() {
+        let one = frame & (1 << (DSHOT_FRAME_BITS - 1 - i)) != 0;
+        *duty = if one {
+            (max_duty as u32 * 3 / 4) as u16
+        } else {
+            (max_duty as u32 * 3 / 8) as u16
+        };
+    }
+    duties
+}
+
+// Sends a 16-bit frame; the bit timing (DShot150..1200) is up to the HAL.
+pub trait DshotOutput {
+    type Error;
+    fn send_frame(&mut self, frame: u16) -> Result<(), Self::Error>;
+}
+
+// An ESC driven by DShot. Frames have to be sent continuously, so `set_speed`
+// should be called at a fixed rate, otherwise the ESC disarms.
+pub struct DshotMotor<O: DshotOutput> {
+    output: O,
+    // 3D mode, with the lower half of the throttle range spinning backwards.
+    bidirectional_3d: bool,
+    bidirectional_telemetry: bool,
+    telemetry: bool,
+}
+
+impl<O: DshotOutput> DshotMotor<O> {
+    pub fn new(output: O, bidirectional_3d: bool, bidirectional_telemetry: bool) -> Self {
+        Self {
+            output,
+            bidirectional_3d,
+            bidirectional_telemetry,
+            telemetry: false,
+        }
+    }
+    // Requests telemetry with every following frame.
+    pub fn set_telemetry(&mut self, telemetry: bool) {
+        self.telemetry = telemetry;
+    }
+    pub fn send_command(&mut self, command: DshotCommand) -> Result<(), O::Error> {
+        // Commands must always have the telemetry bit set.
+        self.output.send_frame(dshot_frame(
+            command as u16,
+            true,
+            self.bidirectional_telemetry,
+        ))
+    }
+    pub fn into_inner(self) -> O {
+        self.output
+    }
+    fn throttle(&self, speed: Duty) -> u16 {
+        let speed = finite_speed(speed).value().clamp(-1., 1.);
+        let (min, max) = if !self.bidirectional_3d {
+            (DSHOT_THROTTLE_MIN, DSHOT_THROTTLE_MAX)
+        } else if speed >= 0. {
+            (DSHOT_3D_NEUTRAL + 1, DSHOT_THROTTLE_MAX)
+        } else {
+            (DSHOT_THROTTLE_MIN, DSHOT_3D_NEUTRAL)
+        };
+        if speed == 0. || (!self.bidirectional_3d && speed < 0.) {
+            return DshotCommand::MotorStop as u16;
+        }
+        min + (speed.abs() * (max - min) as f32) as u16
+    }
+}
+
+impl<O: DshotOutput> Motor for DshotMotor<O> {
+    type Error = O::Error;
+    // Negative speeds stop the motor unless 3D mode is enabled.
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        let frame = dshot_frame(
+            self.throttle(speed),
+            self.telemetry,
+            self.bidirectional_telemetry,
+        );
+        self.output.send_frame(frame)
+    }
+    // The ESC's own braking behaviour on stop is configured in its firmware.
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.set_speed(Duty::ZERO)
+    }
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.set_speed(Duty::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MockOutput {
+        frame: Option<u16>,
+    }
+
+    impl DshotOutput for MockOutput {
+        type Error = Infallible;
+        fn send_frame(&mut self, frame: u16) -> Result<(), Self::Error> {
+            self.frame = Some(frame);
+            Ok(())
+        }
+    }
+
+    fn assert_throttles(motor: &mut DshotMotor<MockOutput>, throttles: &[(f32, u16)]) {
+        for &(speed, throttle) in throttles {
+            motor.set_speed(Duty::new(speed)).unwrap();
+            assert_eq!(
+                motor.output.frame,
+                Some(dshot_frame(throttle, false, false)),
+                "{speed}"
+            );
+        }
+    }
+
+    #[test]
+    fn builds_frames() {
+        // The example from the DShot protocol description.
+        assert_eq!(dshot_frame(1046, false, false), 0b1000001011000110);
+        assert_eq!(dshot_frame(1046, true, false), 0b1000001011010111);
+        assert_eq!(dshot_frame(0, false, false), 0);
+        assert_eq!(dshot_frame(DshotCommand::Beep1 as u16, true, false), 0x0033);
+        // Values above the throttle range are clamped.
+        assert_eq!(
+            dshot_frame(5000, false, false),
+            dshot_frame(2047, false, false)
+        );
+    }
+
+    #[test]
+    fn inverts_bidirectional_crc() {
+        for value in [0, 48, 1046, 2047] {
+            for telemetry in [false, true] {
+                let normal = dshot_frame(value, telemetry, false);
+                let bidirectional = dshot_frame(value, telemetry, true);
+                assert_eq!(normal & !0x0F, bidirectional & !0x0F);
+                assert_eq!(normal & 0x0F, !bidirectional & 0x0F);
+            }
+        }
+        assert_eq!(dshot_frame(1046, false, true), 0b1000001011001001);
+    }
+
+    #[test]
+    fn encodes_bits_as_duties() {
+        let duties = dshot_duties(0b1000001011000110, 80);
+        let bits = duties.map(|duty| match duty {
+            60 => 1,
+            30 => 0,
+            _ => panic!("unexpected duty {duty}"),
+        });
+        assert_eq!(bits, [1, 0, 0, 0, 0, 0, 1, 0, 1, 1, 0, 0, 0, 1, 1, 0]);
+        assert_eq!(dshot_duties(0xFFFF, u16::MAX), [49151; 16]);
+    }
+
+    #[test]
+    fn maps_speed_to_throttle() {
+        let mut motor = DshotMotor::new(MockOutput::default(), false, false);
+        assert_throttles(
+            &mut motor,
+            &[
+                (0., 0),
+                (1., 2047),
+                (0.5, 1047),
+                (0.001, 49),
+                // Reverse needs 3D mode, so it stops.
+                (-0.5, 0),
+                (-1., 0),
+                (f32::NAN, 0),
+            ],
+        );
+        motor.set_speed(Duty::new(1.)).unwrap();
+        motor.brake().unwrap();
+        assert_eq!(motor.output.frame, Some(0));
+        motor.set_speed(Duty::new(1.)).unwrap();
+        motor.coast().unwrap();
+        assert_eq!(motor.output.frame, Some(0));
+    }
+
+    #[test]
+    fn maps_speed_to_3d_throttle() {
+        let mut motor = DshotMotor::new(MockOutput::default(), true, false);
+        assert_throttles(
+            &mut motor,
+            &[
+                (0., 0),
+                // Forward above the neutral point, reverse below it.
+                (0.001, 1048),
+                (0.5, 1547),
+                (1., 2047),
+                (-0.001, 48),
+                (-0.5, 547),
+                (-1., 1047),
+                (f32::NAN, 0),
+            ],
+        );
+    }
+}
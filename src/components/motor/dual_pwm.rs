@@ -0,0 +1,134 @@
+use core::convert::Infallible;
+
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::units::Duty;
+
+use super::{duty_from_speed, finite_speed, Motor};
+
+// What the bridge does during the off part of each PWM period.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Decay {
+    // The motor coasts, giving a less linear speed response.
+    Fast,
+    // The motor is shorted (braked), which is what most DRV88xx datasheets recommend.
+    #[default]
+    Slow,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DualPwmMotorError<IN1, IN2> {
+    In1(IN1),
+    In2(IN2),
+}
+
+impl From<DualPwmMotorError<Infallible, Infallible>> for Infallible {
+    fn from(value: DualPwmMotorError<Infallible, Infallible>) -> Self {
+        match value {
+            DualPwmMotorError::In1(e) | DualPwmMotorError::In2(e) => e,
+        }
+    }
+}
+
+// IN1/IN2 drivers such as the DRV8871, where each half bridge gets its own PWM.
+pub struct DualPwmMotor<IN1: SetDutyCycle, IN2: SetDutyCycle> {
+    in1: IN1,
+    in2: IN2,
+    decay: Decay,
+}
+
+impl<IN1: SetDutyCycle, IN2: SetDutyCycle> DualPwmMotor<IN1, IN2> {
+    pub fn new(in1: IN1, in2: IN2, decay: Decay) -> Self {
+        Self { in1, in2, decay }
+    }
+    fn set(&mut self, in1: u16, in2: u16) -> Result<(), DualPwmMotorError<IN1::Error, IN2::Error>> {
+        self.in1
+            .set_duty_cycle(in1)
+            .map_err(DualPwmMotorError::In1)?;
+        self.in2.set_duty_cycle(in2).map_err(DualPwmMotorError::In2)
+    }
+}
+
+impl<IN1: SetDutyCycle, IN2: SetDutyCycle> Motor for DualPwmMotor<IN1, IN2> {
+    type Error = DualPwmMotorError<IN1::Error, IN2::Error>;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        let speed = finite_speed(speed);
+        let max1 = self.in1.max_duty_cycle();
+        let max2 = self.in2.max_duty_cycle();
+        match (self.decay, speed >= Duty::ZERO) {
+            (Decay::Fast, true) => self.set(duty_from_speed(speed, max1), 0),
+            (Decay::Fast, false) => self.set(0, duty_from_speed(speed, max2)),
+            // In slow decay the PWM'd input is inverted: the bridge brakes while it is high.
+            (Decay::Slow, true) => self.set(max1, max2 - duty_from_speed(speed, max2)),
+            (Decay::Slow, false) => self.set(max1 - duty_from_speed(speed, max1), max2),
+        }
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        let (max1, max2) = (self.in1.max_duty_cycle(), self.in2.max_duty_cycle());
+        self.set(max1, max2)
+    }
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.set(0, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockPwm;
+
+    fn motor(decay: Decay) -> DualPwmMotor<MockPwm, MockPwm> {
+        DualPwmMotor::new(MockPwm::new(1000), MockPwm::new(1000), decay)
+    }
+
+    fn duties(motor: &DualPwmMotor<MockPwm, MockPwm>) -> (u16, u16) {
+        (motor.in1.duty, motor.in2.duty)
+    }
+
+    #[test]
+    fn stops_on_nan_speed() {
+        let mut fast = motor(Decay::Fast);
+        fast.set_speed(Duty::new(f32::NAN)).unwrap();
+        assert_eq!(duties(&fast), (0, 0));
+
+        let mut slow = motor(Decay::Slow);
+        slow.set_speed(Duty::new(f32::NAN)).unwrap();
+        assert_eq!(duties(&slow), (1000, 1000));
+    }
+
+    #[test]
+    fn fast_decay_drives_one_input() {
+        let mut motor = motor(Decay::Fast);
+        motor.set_speed(Duty::new(0.3)).unwrap();
+        assert_eq!(duties(&motor), (300, 0));
+        motor.set_speed(Duty::new(-0.3)).unwrap();
+        assert_eq!(duties(&motor), (0, 300));
+        motor.set_speed(Duty::ZERO).unwrap();
+        assert_eq!(duties(&motor), (0, 0));
+    }
+
+    #[test]
+    fn slow_decay_inverts_other_input() {
+        let mut motor = motor(Decay::Slow);
+        motor.set_speed(Duty::new(0.3)).unwrap();
+        assert_eq!(duties(&motor), (1000, 700));
+        motor.set_speed(Duty::new(-0.3)).unwrap();
+        assert_eq!(duties(&motor), (700, 1000));
+        motor.set_speed(Duty::MAX).unwrap();
+        assert_eq!(duties(&motor), (1000, 0));
+        motor.set_speed(Duty::ZERO).unwrap();
+        assert_eq!(duties(&motor), (1000, 1000));
+    }
+
+    #[test]
+    fn brake_and_coast() {
+        for decay in [Decay::Fast, Decay::Slow] {
+            let mut motor = motor(decay);
+            motor.set_speed(Duty::new(0.5)).unwrap();
+            motor.brake().unwrap();
+            assert_eq!(duties(&motor), (1000, 1000));
+            motor.coast().unwrap();
+            assert_eq!(duties(&motor), (0, 0));
+        }
+    }
+}
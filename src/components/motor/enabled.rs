@@ -0,0 +1,153 @@
+use embedded_hal::digital::{InputPin, OutputPin};
+
+use crate::units::Duty;
+
+use super::Motor;
+
+#[derive(Debug, PartialEq)]
+pub enum EnabledMotorError<M, EN, FAULT> {
+    Motor(M),
+    Enable(EN),
+    FaultPin(FAULT),
+    // The driver reports a fault (overcurrent, overtemperature, undervoltage...).
+    Faulted,
+}
+
+// Adds an enable/sleep pin and a fault input to any motor driver. The driver is
+// disabled to coast, and commands are refused while it reports a fault.
+pub struct EnabledMotor<M: Motor, EN: OutputPin, FAULT: InputPin> {
+    motor: M,
+    enable: EN,
+    fault: FAULT,
+    fault_active_low: bool,
+}
+
+impl<M: Motor, EN: OutputPin, FAULT: InputPin> EnabledMotor<M, EN, FAULT> {
+    // Most drivers have an open-drain nFAULT output, i.e. `fault_active_low = true`.
+    pub fn new(motor: M, enable: EN, fault: FAULT, fault_active_low: bool) -> Self {
+        Self {
+            motor,
+            enable,
+            fault,
+            fault_active_low,
+        }
+    }
+    pub fn is_faulted(&mut self) -> Result<bool, FAULT::Error> {
+        if self.fault_active_low {
+            self.fault.is_low()
+        } else {
+            self.fault.is_high()
+        }
+    }
+    pub fn disable(&mut self) -> Result<(), EN::Error> {
+        self.enable.set_low()
+    }
+    pub fn into_inner(self) -> (M, EN, FAULT) {
+        (self.motor, self.enable, self.fault)
+    }
+    fn enable(&mut self) -> Result<(), <Self as Motor>::Error> {
+        if self.is_faulted().map_err(EnabledMotorError::FaultPin)? {
+            self.disable().map_err(EnabledMotorError::Enable)?;
+            return Err(EnabledMotorError::Faulted);
+        }
+        self.enable.set_high().map_err(EnabledMotorError::Enable)
+    }
+}
+
+impl<M: Motor, EN: OutputPin, FAULT: InputPin> Motor for EnabledMotor<M, EN, FAULT> {
+    type Error = EnabledMotorError<M::Error, EN::Error, FAULT::Error>;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        self.enable()?;
+        self.motor
+            .set_speed(speed)
+            .map_err(EnabledMotorError::Motor)
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.enable()?;
+        self.motor.brake().map_err(EnabledMotorError::Motor)
+    }
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.motor.coast().map_err(EnabledMotorError::Motor)?;
+        self.disable().map_err(EnabledMotorError::Enable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMotor, MockMotorError, MockPin, MotorState};
+
+    // An active-low nFAULT input, released (high) unless a test asserts it.
+    fn motor() -> EnabledMotor<MockMotor, MockPin, MockPin> {
+        EnabledMotor::new(
+            MockMotor::default(),
+            MockPin::default(),
+            MockPin { high: true },
+            true,
+        )
+    }
+
+    #[test]
+    fn enables_before_commanding() {
+        let mut running = motor();
+        running.set_speed(Duty::new(0.5)).unwrap();
+        assert!(running.enable.high);
+        assert_eq!(running.motor.state, MotorState::Speed(Duty::new(0.5)));
+
+        let mut braking = motor();
+        braking.brake().unwrap();
+        assert!(braking.enable.high);
+        assert_eq!(braking.motor.state, MotorState::Brake);
+
+        // The driver is already enabled when the motor fails to take the command.
+        let mut failing = motor();
+        failing.motor.fail = true;
+        assert_eq!(
+            failing.set_speed(Duty::MAX),
+            Err(EnabledMotorError::Motor(MockMotorError))
+        );
+        assert!(failing.enable.high);
+    }
+
+    #[test]
+    fn coast_disables_driver() {
+        let mut motor = motor();
+        motor.set_speed(Duty::MAX).unwrap();
+        motor.coast().unwrap();
+        assert!(!motor.enable.high);
+        assert_eq!(motor.motor.state, MotorState::Coast);
+    }
+
+    #[test]
+    fn refuses_commands_while_faulted() {
+        let mut motor = motor();
+        motor.set_speed(Duty::new(0.5)).unwrap();
+        motor.fault.high = false;
+        assert_eq!(motor.is_faulted(), Ok(true));
+        assert_eq!(motor.set_speed(Duty::MAX), Err(EnabledMotorError::Faulted));
+        assert_eq!(motor.brake(), Err(EnabledMotorError::Faulted));
+        assert!(!motor.enable.high);
+        assert_eq!(motor.motor.calls, 1);
+
+        // Coasting is always allowed.
+        motor.coast().unwrap();
+        assert_eq!(motor.motor.state, MotorState::Coast);
+
+        motor.fault.high = true;
+        motor.set_speed(Duty::MAX).unwrap();
+        assert!(motor.enable.high);
+    }
+
+    #[test]
+    fn active_high_fault() {
+        let mut motor = EnabledMotor::new(
+            MockMotor::default(),
+            MockPin::default(),
+            MockPin { high: true },
+            false,
+        );
+        assert_eq!(motor.set_speed(Duty::MAX), Err(EnabledMotorError::Faulted));
+        motor.fault.high = false;
+        motor.set_speed(Duty::MAX).unwrap();
+    }
+}
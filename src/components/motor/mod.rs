@@ -0,0 +1,203 @@
+pub mod antiphase;
+pub mod dshot;
+pub mod dual_pwm;
+pub mod enabled;
+pub mod protection;
+pub mod remote;
+pub mod robomaster;
+pub mod shaping;
+
+use core::{convert::Infallible, ops::Not};
+
+use embedded_hal::{digital::OutputPin, pwm::SetDutyCycle};
+#[allow(unused_imports)]
+use micromath::F32Ext;
+use num_enum::{FromPrimitive, IntoPrimitive};
+
+use crate::units::{AngularVelocity, Duty};
+
+#[derive(IntoPrimitive, FromPrimitive, PartialEq)]
+#[repr(u8)]
+pub enum Dir {
+    Cw = 0,
+    #[num_enum(default)]
+    Ccw = 1,
+}
+
+impl Not for Dir {
+    type Output = Self;
+    fn not(self) -> Self::Output {
+        if self == Dir::Cw {
+            Dir::Ccw
+        } else {
+            Dir::Cw
+        }
+    }
+}
+
+pub trait Motor {
+    type Error;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error>;
+    // Shorts the motor terminals so it stops quickly.
+    fn brake(&mut self) -> Result<(), Self::Error>;
+    // Leaves the motor terminals floating so it spins down freely.
+    fn coast(&mut self) -> Result<(), Self::Error>;
+
+    fn cw(&mut self, duty: Duty) -> Result<(), Self::Error> {
+        self.set_speed(duty.abs())
+    }
+    fn ccw(&mut self, duty: Duty) -> Result<(), Self::Error> {
+        self.set_speed(-duty.abs())
+    }
+    fn run(&mut self, duty: Duty, dir: impl Into<Dir>) -> Result<(), Self::Error> {
+        if dir.into() == Dir::Cw {
+            self.cw(duty)
+        } else {
+            self.ccw(duty)
+        }
+    }
+    fn inverted(self) -> Inverted<Self>
+    where
+        Self: Sized,
+    {
+        Inverted(self)
+    }
+}
+
+// Motors with their own speed loop, e.g. on a remote node, that can be given a
+// target speed instead of a duty.
+pub trait VelocityMotor: Motor {
+    fn set_velocity(&mut self, velocity: AngularVelocity) -> Result<(), Self::Error>;
+}
+
+// Drivers treat a NaN or infinite speed, e.g. from a diverged controller, as a stop
+// rather than letting it pick a direction.
+pub(crate) fn finite_speed(speed: Duty) -> Duty {
+    if speed.value().is_finite() {
+        speed
+    } else {
+        Duty::ZERO
+    }
+}
+
+// Scales the magnitude of a normalized speed to the PWM's duty cycle range.
+pub(crate) fn duty_from_speed(speed: Duty, max_duty: u16) -> u16 {
+    (speed.value().abs() * max_duty as f32).round() as u16
+}
+
+// Swaps the direction of a motor, e.g. one mounted mirrored on the other side.
+pub struct Inverted<M: Motor>(M);
+
+impl<M: Motor> Inverted<M> {
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<M: Motor> Motor for Inverted<M> {
+    type Error = M::Error;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        self.0.set_speed(-speed)
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.0.brake()
+    }
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.0.coast()
+    }
+}
+
+impl<M: VelocityMotor> VelocityMotor for Inverted<M> {
+    fn set_velocity(&mut self, velocity: AngularVelocity) -> Result<(), Self::Error> {
+        self.0.set_velocity(-velocity)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DcMotorError<PWM, DIR> {
+    Pwm(PWM),
+    Dir(DIR),
+}
+
+// Lets `unwrap_infallible` be used on drivers whose HAL can't fail.
+impl From<DcMotorError<Infallible, Infallible>> for Infallible {
+    fn from(value: DcMotorError<Infallible, Infallible>) -> Self {
+        match value {
+            DcMotorError::Pwm(e) | DcMotorError::Dir(e) => e,
+        }
+    }
+}
+
+pub struct DcMotor<PWM: SetDutyCycle, DIR: OutputPin> {
+    pwm: PWM,
+    dir: DIR,
+}
+
+impl<PWM: SetDutyCycle, DIR: OutputPin> DcMotor<PWM, DIR> {
+    pub fn new(pwm: PWM, dir: DIR) -> Self {
+        Self { pwm, dir }
+    }
+}
+
+impl<PWM: SetDutyCycle, DIR: OutputPin> Motor for DcMotor<PWM, DIR> {
+    type Error = DcMotorError<PWM::Error, DIR::Error>;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        let speed = finite_speed(speed);
+        if speed >= Duty::ZERO {
+            self.dir.set_low().map_err(DcMotorError::Dir)?;
+        } else {
+            self.dir.set_high().map_err(DcMotorError::Dir)?;
+        }
+        let duty = duty_from_speed(speed, self.pwm.max_duty_cycle());
+        self.pwm.set_duty_cycle(duty).map_err(DcMotorError::Pwm)
+    }
+    // PWM/DIR drivers can't tell braking from coasting; what happens at zero duty
+    // depends on the driver.
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.pwm
+            .set_duty_cycle_fully_off()
+            .map_err(DcMotorError::Pwm)
+    }
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.pwm
+            .set_duty_cycle_fully_off()
+            .map_err(DcMotorError::Pwm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPin, MockPwm};
+
+    fn motor() -> DcMotor<MockPwm, MockPin> {
+        DcMotor::new(MockPwm::new(1000), MockPin::default())
+    }
+
+    #[test]
+    fn sets_direction_and_duty() {
+        let mut motor = motor();
+        motor.set_speed(Duty::new(0.25)).unwrap();
+        assert_eq!((motor.pwm.duty, motor.dir.high), (250, false));
+        motor.set_speed(Duty::new(-0.5)).unwrap();
+        assert_eq!((motor.pwm.duty, motor.dir.high), (500, true));
+    }
+
+    #[test]
+    fn stops_on_nan_speed() {
+        for speed in [f32::NAN, -f32::NAN] {
+            let mut motor = motor();
+            motor.set_speed(Duty::new(-1.)).unwrap();
+            motor.set_speed(Duty::new(speed)).unwrap();
+            assert_eq!((motor.pwm.duty, motor.dir.high), (0, false));
+        }
+    }
+
+    #[test]
+    fn inverted_negates_speed() {
+        let mut motor = motor().inverted();
+        motor.set_speed(Duty::new(0.5)).unwrap();
+        let motor = motor.into_inner();
+        assert_eq!((motor.pwm.duty, motor.dir.high), (500, true));
+    }
+}
@@ -0,0 +1,299 @@
+use core::time::Duration;
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+
+use crate::{
+    components::current_sensor::CurrentSensor,
+    units::{AngularVelocity, Duty},
+};
+
+use super::Motor;
+
+#[derive(IntoPrimitive, FromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Fault {
+    Overcurrent = 1,
+    Stall = 2,
+    // The I²t budget above the rated current is used up.
+    Overheat = 3,
+    #[num_enum(default)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaultAction {
+    // Coasts the motor and refuses commands until the fault is cleared.
+    Cut,
+    // Scales every command by the given factor until the fault is cleared.
+    Derate(f32),
+}
+
+// Currents are in amperes and compared by magnitude.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProtectionConfig {
+    // Weight of a new sample in the exponential moving average, 0.0..=1.0.
+    pub filter: f32,
+    pub max_current: f32,
+    // A stall is a current above `stall_current` while the speed stays below
+    // `stall_velocity` for `stall_time`.
+    pub stall_current: f32,
+    pub stall_velocity: AngularVelocity,
+    pub stall_time: Duration,
+    // Current the motor can carry continuously, and the A²s it may accumulate
+    // above it.
+    pub rated_current: f32,
+    pub i2t_limit: f32,
+    pub action: FaultAction,
+    // Frequency `update` is called at, must be positive.
+    pub update_freq: f32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProtectedMotorError<M, S> {
+    Motor(M),
+    Sensor(S),
+    Faulted(Fault),
+}
+
+pub struct ProtectedMotor<M: Motor, S: CurrentSensor> {
+    motor: M,
+    sensor: S,
+    config: ProtectionConfig,
+    current: f32,
+    stalled: Duration,
+    i2t: f32,
+    fault: Option<Fault>,
+    speed: Duty,
+}
+
+impl<M: Motor, S: CurrentSensor> ProtectedMotor<M, S> {
+    pub fn new(motor: M, sensor: S, config: ProtectionConfig) -> Self {
+        assert!(config.update_freq > 0. && config.update_freq.is_finite());
+        Self {
+            motor,
+            sensor,
+            config,
+            current: 0.,
+            stalled: Duration::ZERO,
+            i2t: 0.,
+            fault: None,
+            speed: Duty::ZERO,
+        }
+    }
+    pub fn set_config(&mut self, config: ProtectionConfig) {
+        assert!(config.update_freq > 0. && config.update_freq.is_finite());
+        self.config = config;
+    }
+    // Filtered current magnitude.
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+    pub fn i2t(&self) -> f32 {
+        self.i2t
+    }
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+    // Whether a fault cut the motor, which then rejects speeds until the fault is
+    // cleared.
+    pub fn is_cut(&self) -> bool {
+        self.check().is_err()
+    }
+    // The I²t budget is thermal state and is kept.
+    pub fn clear_fault(&mut self) {
+        self.fault = None;
+        self.stalled = Duration::ZERO;
+    }
+    pub fn into_inner(self) -> (M, S) {
+        (self.motor, self.sensor)
+    }
+    // Samples the current and checks it against the limits, given the measured
+    // velocity. Returns the fault if one has just tripped. Overcurrent is checked on
+    // the raw sample so a short trips at once; stall and I²t use the filtered
+    // current.
+    pub fn update(
+        &mut self,
+        velocity: AngularVelocity,
+    ) -> Result<Option<Fault>, <Self as Motor>::Error> {
+        let dt = 1. / self.config.update_freq;
+        let sample = self
+            .sensor
+            .current()
+            .map_err(ProtectedMotorError::Sensor)?
+            .abs();
+        self.current += (sample - self.current) * self.config.filter;
+
+        let rated = self.config.rated_current;
+        self.i2t = (self.i2t + (self.current * self.current - rated * rated) * dt).max(0.);
+
+        if self.current > self.config.stall_current && velocity.abs() < self.config.stall_velocity {
+            self.stalled += Duration::from_secs_f32(dt);
+        } else {
+            self.stalled = Duration::ZERO;
+        }
+
+        if self.fault.is_some() {
+            return Ok(None);
+        }
+        let fault = if sample > self.config.max_current {
+            Fault::Overcurrent
+        } else if self.stalled >= self.config.stall_time {
+            Fault::Stall
+        } else if self.i2t > self.config.i2t_limit {
+            Fault::Overheat
+        } else {
+            return Ok(None);
+        };
+        self.fault = Some(fault);
+        match self.config.action {
+            FaultAction::Cut => self.motor.coast(),
+            FaultAction::Derate(factor) => self.motor.set_speed(self.speed * factor),
+        }
+        .map_err(ProtectedMotorError::Motor)?;
+        Ok(Some(fault))
+    }
+    fn check(&self) -> Result<f32, <Self as Motor>::Error> {
+        match (self.fault, self.config.action) {
+            (None, _) => Ok(1.),
+            (Some(fault), FaultAction::Cut) => Err(ProtectedMotorError::Faulted(fault)),
+            (Some(_), FaultAction::Derate(factor)) => Ok(factor),
+        }
+    }
+}
+
+impl<M: Motor, S: CurrentSensor> Motor for ProtectedMotor<M, S> {
+    type Error = ProtectedMotorError<M::Error, S::Error>;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        let factor = self.check()?;
+        self.speed = speed;
+        self.motor
+            .set_speed(speed * factor)
+            .map_err(ProtectedMotorError::Motor)
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.speed = Duty::ZERO;
+        self.motor.brake().map_err(ProtectedMotorError::Motor)
+    }
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.speed = Duty::ZERO;
+        self.motor.coast().map_err(ProtectedMotorError::Motor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockCurrentSensor, MockMotor, MotorState};
+
+    fn config() -> ProtectionConfig {
+        ProtectionConfig {
+            filter: 0.1,
+            max_current: 10.,
+            stall_current: 5.,
+            stall_velocity: AngularVelocity::from_rpm(10.),
+            stall_time: Duration::from_millis(100),
+            rated_current: 3.,
+            i2t_limit: 20.,
+            action: FaultAction::Cut,
+            update_freq: 100.,
+        }
+    }
+
+    fn rpm(rpm: f32) -> AngularVelocity {
+        AngularVelocity::from_rpm(rpm)
+    }
+
+    fn motor(config: ProtectionConfig) -> ProtectedMotor<MockMotor, MockCurrentSensor> {
+        ProtectedMotor::new(MockMotor::default(), MockCurrentSensor::default(), config)
+    }
+
+    #[test]
+    fn trips_overcurrent_on_first_sample() {
+        let mut motor = motor(config());
+        motor.set_speed(Duty::MAX).unwrap();
+        motor.sensor.current = -12.;
+        assert_eq!(motor.update(rpm(1000.)), Ok(Some(Fault::Overcurrent)));
+        // The filtered current is still far below the limit.
+        assert!(motor.current() < config().max_current);
+        assert_eq!(motor.motor.state, MotorState::Coast);
+        assert_eq!(
+            motor.set_speed(Duty::MAX),
+            Err(ProtectedMotorError::Faulted(Fault::Overcurrent))
+        );
+        assert_eq!(motor.update(rpm(1000.)), Ok(None));
+
+        motor.clear_fault();
+        motor.sensor.current = 0.;
+        motor.update(rpm(1000.)).unwrap();
+        motor.set_speed(Duty::MAX).unwrap();
+    }
+
+    #[test]
+    fn trips_stall_after_stall_time() {
+        let config = ProtectionConfig {
+            filter: 1.,
+            i2t_limit: f32::INFINITY,
+            ..config()
+        };
+        let mut motor = motor(config);
+        motor.sensor.current = 6.;
+        for _ in 0..9 {
+            assert_eq!(motor.update(rpm(5.)), Ok(None));
+        }
+        // Turning resets the timer.
+        assert_eq!(motor.update(rpm(100.)), Ok(None));
+        for _ in 0..9 {
+            assert_eq!(motor.update(rpm(-5.)), Ok(None));
+        }
+        assert_eq!(motor.update(rpm(-5.)), Ok(Some(Fault::Stall)));
+    }
+
+    #[test]
+    fn trips_overheat_from_i2t() {
+        let config = ProtectionConfig {
+            filter: 1.,
+            stall_current: f32::INFINITY,
+            ..config()
+        };
+        let mut motor = motor(config);
+        // (5² - 3²) A² * 10 ms = 0.16 A²s per update.
+        motor.sensor.current = 5.;
+        for _ in 0..125 {
+            assert_eq!(motor.update(rpm(0.)), Ok(None));
+        }
+        assert_eq!(motor.update(rpm(0.)), Ok(Some(Fault::Overheat)));
+
+        // Below the rated current the budget recovers.
+        motor.clear_fault();
+        motor.sensor.current = 0.;
+        for _ in 0..10 {
+            motor.update(rpm(0.)).unwrap();
+        }
+        assert!(motor.i2t() < config.i2t_limit);
+    }
+
+    #[test]
+    fn derates_instead_of_cutting() {
+        let config = ProtectionConfig {
+            action: FaultAction::Derate(0.5),
+            ..config()
+        };
+        let mut motor = motor(config);
+        motor.set_speed(Duty::new(0.8)).unwrap();
+        motor.sensor.current = 20.;
+        assert_eq!(motor.update(rpm(0.)), Ok(Some(Fault::Overcurrent)));
+        assert_eq!(motor.motor.state, MotorState::Speed(Duty::new(0.4)));
+        motor.set_speed(Duty::new(-0.6)).unwrap();
+        assert_eq!(motor.motor.state, MotorState::Speed(Duty::new(-0.3)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_update_freq() {
+        motor(ProtectionConfig {
+            update_freq: 0.,
+            ..config()
+        });
+    }
+}
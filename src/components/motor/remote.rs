@@ -0,0 +1,203 @@
+use crate::{
+    node::{
+        id::Id,
+        message::{CanMessage, Message},
+        motor_command::MotorCommand,
+        transport::Transport,
+    },
+    units::{AngularVelocity, Duty},
+};
+
+use super::{finite_speed, Motor, VelocityMotor};
+
+// A channel of a `MotorNode` on another CAN node, driven like a local motor.
+pub struct RemoteMotor<T: Transport<8>> {
+    transport: T,
+    from: Id,
+    to: Id,
+    channel: u8,
+    measured_velocity: Option<AngularVelocity>,
+}
+
+impl<T: Transport<8>> RemoteMotor<T> {
+    pub fn new(transport: T, from: impl Into<Id>, to: impl Into<Id>, channel: u8) -> Self {
+        Self {
+            transport,
+            from: from.into(),
+            to: to.into(),
+            channel,
+            measured_velocity: None,
+        }
+    }
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+    // The last speed reported by the node, if any has been handled yet.
+    pub fn measured_velocity(&self) -> Option<AngularVelocity> {
+        self.measured_velocity
+    }
+    // Feeds a received message to the motor, returning whether it was the speed
+    // feedback of this channel.
+    pub fn handle<const N: usize>(&mut self, message: &Message<N>) -> bool {
+        if message.from() != self.to {
+            return false;
+        }
+        match MotorCommand::from_message(message) {
+            Some(MotorCommand::NotifyRpm { channel, velocity }) if channel == self.channel => {
+                self.measured_velocity = Some(velocity);
+                true
+            }
+            _ => false,
+        }
+    }
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+    fn send(&mut self, command: MotorCommand) -> Result<(), T::Error> {
+        // Motor command payloads always fit in a CAN frame.
+        let message: CanMessage = command.into_message(self.from, self.to).unwrap();
+        self.transport.send(message)
+    }
+}
+
+// The node rejects NaN and infinite values and would keep running at the last
+// command, so they are sent as a stop instead.
+impl<T: Transport<8>> Motor for RemoteMotor<T> {
+    type Error = T::Error;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        self.send(MotorCommand::SetDuty {
+            channel: self.channel,
+            duty: finite_speed(speed),
+        })
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.send(MotorCommand::Stop {
+            channel: self.channel,
+        })
+    }
+    // There is no coast command, so this only zeroes the duty. Whether the motor
+    // then coasts depends on the driver of the node.
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.set_speed(Duty::ZERO)
+    }
+}
+
+impl<T: Transport<8>> VelocityMotor for RemoteMotor<T> {
+    fn set_velocity(&mut self, velocity: AngularVelocity) -> Result<(), Self::Error> {
+        if !velocity.rpm().is_finite() {
+            return self.brake();
+        }
+        self.send(MotorCommand::SetRpm {
+            channel: self.channel,
+            velocity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock::MockTransport, node::command::Command};
+
+    const HOST: u8 = 1;
+    const NODE: u8 = 3;
+
+    fn remote() -> RemoteMotor<MockTransport<8>> {
+        RemoteMotor::new(MockTransport::default(), HOST, NODE, 2)
+    }
+
+    fn sent(motor: RemoteMotor<MockTransport<8>>) -> std::vec::Vec<(Command, std::vec::Vec<u8>)> {
+        let transport = motor.into_inner();
+        for message in &transport.sent {
+            assert_eq!(message.from(), Id::from(HOST));
+            assert_eq!(message.to(), Id::from(NODE));
+        }
+        transport
+            .sent
+            .iter()
+            .map(|message| (message.command(), message.payload().to_vec()))
+            .collect()
+    }
+
+    fn channel_value(value: f32) -> std::vec::Vec<u8> {
+        [&[2][..], &value.to_be_bytes()].concat()
+    }
+
+    #[test]
+    fn sends_duty_and_rpm() {
+        let mut motor = remote();
+        motor.set_speed(Duty::new(-0.5)).unwrap();
+        motor
+            .set_velocity(AngularVelocity::from_rpm(1200.))
+            .unwrap();
+        let sent = sent(motor);
+        assert_eq!(sent[0], (Command::SetDuty, channel_value(-0.5)));
+        assert_eq!(sent[1].0, Command::SetRpm);
+        let rpm = f32::from_be_bytes(sent[1].1[1..].try_into().unwrap());
+        assert!((rpm - 1200.).abs() < 1e-2);
+        assert_eq!(sent.len(), 2);
+    }
+
+    #[test]
+    fn brakes_and_coasts() {
+        let mut motor = remote();
+        motor.brake().unwrap();
+        motor.coast().unwrap();
+        assert_eq!(
+            sent(motor),
+            [
+                (Command::Stop, std::vec![2]),
+                (Command::SetDuty, channel_value(0.)),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_on_non_finite_commands() {
+        let mut motor = remote();
+        motor.set_speed(Duty::new(f32::NAN)).unwrap();
+        motor
+            .set_velocity(AngularVelocity::from_rpm(f32::INFINITY))
+            .unwrap();
+        motor
+            .set_velocity(AngularVelocity::from_rpm(f32::NAN))
+            .unwrap();
+        assert_eq!(
+            sent(motor),
+            [
+                (Command::SetDuty, channel_value(0.)),
+                (Command::Stop, std::vec![2]),
+                (Command::Stop, std::vec![2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn handles_own_rpm_feedback() {
+        let mut motor = remote();
+        assert_eq!(motor.measured_velocity(), None);
+        let notify = |channel| MotorCommand::NotifyRpm {
+            channel,
+            velocity: AngularVelocity::from_rpm(60.),
+        };
+        let message: CanMessage = notify(2).into_message(NODE, HOST).unwrap();
+        assert!(motor.handle(&message));
+        let velocity = motor.measured_velocity().unwrap();
+        assert!((velocity.rpm() - 60.).abs() < 1e-3);
+
+        // Other channels, other nodes and other commands are left alone.
+        let other_channel: CanMessage = notify(1).into_message(NODE, HOST).unwrap();
+        let other_node: CanMessage = notify(2).into_message(NODE + 1, HOST).unwrap();
+        let other_command: CanMessage = MotorCommand::SetRpm {
+            channel: 2,
+            velocity: AngularVelocity::ZERO,
+        }
+        .into_message(NODE, HOST)
+        .unwrap();
+        let mut other = remote();
+        for message in [other_channel, other_node, other_command] {
+            assert!(!other.handle(&message));
+        }
+        assert_eq!(other.measured_velocity(), None);
+    }
+}
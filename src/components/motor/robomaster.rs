@@ -0,0 +1,300 @@
+use core::cell::RefCell;
+
+use embedded_can::{blocking::Can, Frame, Id, StandardId};
+
+use crate::units::Duty;
+
+use super::Motor;
+
+const COMMAND_ID_LOW: u16 = 0x200;
+const COMMAND_ID_HIGH: u16 = 0x1FF;
+const FEEDBACK_ID_BASE: u16 = 0x200;
+const MOTOR_COUNT: usize = 8;
+const ENCODER_RESOLUTION: u16 = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EscModel {
+    // For the M2006, ±10 A.
+    C610,
+    // For the M3508, ±20 A.
+    C620,
+}
+
+impl EscModel {
+    pub fn max_current(&self) -> f32 {
+        match self {
+            EscModel::C610 => 10.,
+            EscModel::C620 => 20.,
+        }
+    }
+    fn max_command(&self) -> i16 {
+        match self {
+            EscModel::C610 => 10000,
+            EscModel::C620 => 16384,
+        }
+    }
+}
+
+// Sent by every ESC at 1 kHz with the standard ID 0x200 + motor id. The C610
+// doesn't report a temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Feedback {
+    // Rotor angle, 0..8192 for one revolution.
+    pub angle: u16,
+    pub rpm: i16,
+    // Raw torque current in the unit of the current command.
+    pub current: i16,
+    pub temperature: u8,
+}
+
+impl Feedback {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let [a0, a1, r0, r1, c0, c1, temperature, ..] = *data else {
+            return None;
+        };
+        Some(Self {
+            angle: u16::from_be_bytes([a0, a1]),
+            rpm: i16::from_be_bytes([r0, r1]),
+            current: i16::from_be_bytes([c0, c1]),
+            temperature,
+        })
+    }
+    // The motor id (1..=8) a feedback frame is from.
+    pub fn motor_id(id: Id) -> Option<u8> {
+        let Id::Standard(id) = id else {
+            return None;
+        };
+        let motor_id = id.as_raw().checked_sub(FEEDBACK_ID_BASE)?;
+        (1..=MOTOR_COUNT as u16)
+            .contains(&motor_id)
+            .then_some(motor_id as u8)
+    }
+    pub fn rotations(&self) -> f32 {
+        self.angle as f32 / ENCODER_RESOLUTION as f32
+    }
+    pub fn into_array(&self) -> [u8; 8] {
+        let [a0, a1] = self.angle.to_be_bytes();
+        let [r0, r1] = self.rpm.to_be_bytes();
+        let [c0, c1] = self.current.to_be_bytes();
+        [a0, a1, r0, r1, c0, c1, self.temperature, 0]
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RoboMasterError<CAN> {
+    // Motor ids are 1..=8.
+    InvalidMotorId,
+    Can(CAN),
+}
+
+// Current commands for motor ids 1..=4 go out with ID 0x200 and for 5..=8 with
+// 0x1FF, each as four big-endian i16 values.
+pub fn current_command_frame<F: Frame>(high_ids: bool, currents: &[i16; 4]) -> F {
+    let id = if high_ids {
+        COMMAND_ID_HIGH
+    } else {
+        COMMAND_ID_LOW
+    };
+    let mut data = [0; 8];
+    for (bytes, current) in data.chunks_exact_mut(2).zip(currents) {
+        bytes.copy_from_slice(&current.to_be_bytes());
+    }
+    Frame::new(StandardId::new(id).unwrap(), &data).unwrap()
+}
+
+// The ESCs on one CAN bus. Motors share it through a `RefCell`, and every
+// command resends the frame of its group of four.
+pub struct RoboMasterBus<CAN: Can> {
+    can: CAN,
+    currents: [i16; MOTOR_COUNT],
+    feedback: [Option<Feedback>; MOTOR_COUNT],
+}
+
+impl<CAN: Can> RoboMasterBus<CAN> {
+    pub fn new(can: CAN) -> Self {
+        Self {
+            can,
+            currents: [0; MOTOR_COUNT],
+            feedback: [None; MOTOR_COUNT],
+        }
+    }
+    // Stores feedback frames, returning whether `frame` was one.
+    pub fn handle(&mut self, frame: &impl Frame) -> bool {
+        let Some(motor_id) = Feedback::motor_id(frame.id()) else {
+            return false;
+        };
+        let Some(feedback) = Feedback::parse(frame.data()) else {
+            return false;
+        };
+        self.feedback[motor_id as usize - 1] = Some(feedback);
+        true
+    }
+    pub fn feedback(&self, motor_id: u8) -> Option<Feedback> {
+        *self.feedback.get((motor_id as usize).checked_sub(1)?)?
+    }
+    // Sets the raw current command of motor `motor_id` (1..=8) and sends it.
+    pub fn set_current(
+        &mut self,
+        motor_id: u8,
+        current: i16,
+    ) -> Result<(), RoboMasterError<CAN::Error>> {
+        let index = (motor_id as usize)
+            .checked_sub(1)
+            .filter(|index| *index < MOTOR_COUNT)
+            .ok_or(RoboMasterError::InvalidMotorId)?;
+        self.currents[index] = current;
+        let group = index / 4;
+        let currents = self.currents[group * 4..][..4].try_into().unwrap();
+        self.can
+            .transmit(&current_command_frame(group == 1, currents))
+            .map_err(RoboMasterError::Can)
+    }
+    pub fn into_inner(self) -> CAN {
+        self.can
+    }
+}
+
+// A motor behind a C610 or C620. The speed is the normalized torque current, so
+// closing a speed loop is left to e.g. `VelocityControlledMotor`.
+pub struct RoboMasterMotor<'a, CAN: Can> {
+    bus: &'a RefCell<RoboMasterBus<CAN>>,
+    motor_id: u8,
+    model: EscModel,
+}
+
+impl<'a, CAN: Can> RoboMasterMotor<'a, CAN> {
+    // `motor_id` is the id set on the ESC, 1..=8.
+    pub fn new(bus: &'a RefCell<RoboMasterBus<CAN>>, motor_id: u8, model: EscModel) -> Self {
+        assert!((1..=MOTOR_COUNT as u8).contains(&motor_id));
+        Self {
+            bus,
+            motor_id,
+            model,
+        }
+    }
+    pub fn feedback(&self) -> Option<Feedback> {
+        self.bus.borrow().feedback(self.motor_id)
+    }
+    // Torque current in amperes from the last feedback.
+    pub fn current(&self) -> Option<f32> {
+        let feedback = self.feedback()?;
+        Some(feedback.current as f32 / self.model.max_command() as f32 * self.model.max_current())
+    }
+}
+
+impl<CAN: Can> Motor for RoboMasterMotor<'_, CAN> {
+    type Error = RoboMasterError<CAN::Error>;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        let current = speed.value() * self.model.max_command() as f32;
+        self.bus
+            .borrow_mut()
+            .set_current(self.motor_id, current as i16)
+    }
+    // The ESCs have no brake mode, both just command zero current.
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.set_speed(Duty::ZERO)
+    }
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.set_speed(Duty::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockCan, MockFrame};
+
+    fn standard(id: u16) -> Id {
+        Id::Standard(StandardId::new(id).unwrap())
+    }
+
+    #[test]
+    fn parses_feedback() {
+        let data = [0x1F, 0xFF, 0xFC, 0x18, 0x01, 0xF4, 40, 0];
+        let feedback = Feedback::parse(&data).unwrap();
+        assert_eq!(
+            feedback,
+            Feedback {
+                angle: 8191,
+                rpm: -1000,
+                current: 500,
+                temperature: 40,
+            }
+        );
+        assert_eq!(feedback.into_array(), data);
+        assert!((feedback.rotations() - 8191. / 8192.).abs() < 1e-6);
+        assert_eq!(Feedback::parse(&data[..6]), None);
+    }
+
+    #[test]
+    fn maps_feedback_ids() {
+        assert_eq!(Feedback::motor_id(standard(0x201)), Some(1));
+        assert_eq!(Feedback::motor_id(standard(0x208)), Some(8));
+        for id in [0x200, 0x209, 0x1FF, 0x100] {
+            assert_eq!(Feedback::motor_id(standard(id)), None);
+        }
+        let extended = Id::Extended(embedded_can::ExtendedId::new(0x201).unwrap());
+        assert_eq!(Feedback::motor_id(extended), None);
+    }
+
+    #[test]
+    fn builds_current_command_frames() {
+        let frame: MockFrame = current_command_frame(false, &[1, -1, 0x1234, 0]);
+        assert_eq!(frame.id(), standard(0x200));
+        assert_eq!(frame.data(), &[0, 1, 0xFF, 0xFF, 0x12, 0x34, 0, 0]);
+        let frame: MockFrame = current_command_frame(true, &[0, 0, 0, -2]);
+        assert_eq!(frame.id(), standard(0x1FF));
+        assert_eq!(frame.data(), &[0, 0, 0, 0, 0, 0, 0xFF, 0xFE]);
+    }
+
+    #[test]
+    fn sends_group_of_motor() {
+        let mut bus = RoboMasterBus::new(MockCan::default());
+        bus.set_current(2, 100).unwrap();
+        bus.set_current(7, -100).unwrap();
+        bus.set_current(1, 50).unwrap();
+        let can = bus.into_inner();
+        let frames: std::vec::Vec<_> = can.sent.iter().map(|f| (f.id(), f.data())).collect();
+        assert_eq!(
+            frames,
+            [
+                (standard(0x200), &[0, 0, 0, 100, 0, 0, 0, 0][..]),
+                (standard(0x1FF), &[0, 0, 0, 0, 0xFF, 0x9C, 0, 0][..]),
+                (standard(0x200), &[0, 50, 0, 100, 0, 0, 0, 0][..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_motor_id() {
+        let mut bus = RoboMasterBus::new(MockCan::default());
+        assert_eq!(bus.set_current(0, 1), Err(RoboMasterError::InvalidMotorId));
+        assert_eq!(bus.set_current(9, 1), Err(RoboMasterError::InvalidMotorId));
+        assert!(bus.into_inner().sent.is_empty());
+    }
+
+    #[test]
+    fn scales_speed_to_current() {
+        let bus = RefCell::new(RoboMasterBus::new(MockCan::default()));
+        let mut motor = RoboMasterMotor::new(&bus, 1, EscModel::C620);
+        motor.set_speed(Duty::MIN).unwrap();
+        assert_eq!(bus.borrow().currents[0], -16384);
+        let mut motor = RoboMasterMotor::new(&bus, 2, EscModel::C610);
+        motor.set_speed(Duty::new(0.5)).unwrap();
+        assert_eq!(bus.borrow().currents[1], 5000);
+        motor.coast().unwrap();
+        assert_eq!(bus.borrow().currents[1], 0);
+    }
+
+    #[test]
+    fn stores_feedback() {
+        let mut bus = RoboMasterBus::new(MockCan::default());
+        let data = [0, 10, 0, 20, 0, 30, 40, 0];
+        assert!(bus.handle(&MockFrame::new(StandardId::new(0x203).unwrap(), &data).unwrap()));
+        assert!(!bus.handle(&MockFrame::new(StandardId::new(0x301).unwrap(), &data).unwrap()));
+        assert_eq!(bus.feedback(3).map(|f| f.rpm), Some(20));
+        assert_eq!(bus.feedback(1), None);
+        assert_eq!(bus.feedback(0), None);
+        assert_eq!(bus.feedback(9), None);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::units::Duty;
+
+use super::Motor;
+
+// All values are in normalized duty (0.0..=1.0), steps are per call to
+// `set_speed`, i.e. per control tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapingConfig {
+    // Duty at which the motor just starts to overcome static friction. Any nonzero
+    // command is scaled into `deadband..=max_duty`.
+    pub deadband: f32,
+    // Commands smaller than this are treated as a stop.
+    pub min_duty: f32,
+    pub max_duty: f32,
+    // Maximum change of the command per tick.
+    pub max_step: f32,
+    // Smaller step used while accelerating from standstill up to `soft_start_until`.
+    pub soft_start_step: f32,
+    pub soft_start_until: f32,
+}
+
+impl Default for ShapingConfig {
+    fn default() -> Self {
+        Self {
+            deadband: 0.,
+            min_duty: 0.,
+            max_duty: 1.,
+            max_step: f32::INFINITY,
+            soft_start_step: f32::INFINITY,
+            soft_start_until: 0.,
+        }
+    }
+}
+
+impl ShapingConfig {
+    fn is_valid(&self) -> bool {
+        let duty = 0.0..=1.0;
+        duty.contains(&self.deadband)
+            && duty.contains(&self.min_duty)
+            && duty.contains(&self.max_duty)
+            && self.deadband <= self.max_duty
+            && self.max_step >= 0.
+            && self.soft_start_step >= 0.
+            && !self.soft_start_until.is_nan()
+    }
+}
+
+pub struct ShapedMotor<M: Motor> {
+    motor: M,
+    config: ShapingConfig,
+    command: f32,
+}
+
+impl<M: Motor> ShapedMotor<M> {
+    pub fn new(motor: M, config: ShapingConfig) -> Self {
+        assert!(config.is_valid());
+        Self {
+            motor,
+            config,
+            command: 0.,
+        }
+    }
+    pub fn set_config(&mut self, config: ShapingConfig) {
+        assert!(config.is_valid());
+        self.config = config;
+    }
+    // The slew-limited command before deadband compensation.
+    pub fn command(&self) -> f32 {
+        self.command
+    }
+    pub fn into_inner(self) -> M {
+        self.motor
+    }
+    fn step(&self, target: f32) -> f32 {
+        let config = &self.config;
+        let accelerating = target.abs() > self.command.abs() || target * self.command < 0.;
+        let max_step = if accelerating && self.command.abs() < config.soft_start_until {
+            config.soft_start_step.min(config.max_step)
+        } else {
+            config.max_step
+        };
+        self.command + (target - self.command).clamp(-max_step, max_step)
+    }
+    fn output(&self) -> f32 {
+        let config = &self.config;
+        let magnitude = self.command.abs();
+        if magnitude < config.min_duty || magnitude == 0. {
+            return 0.;
+        }
+        let duty = config.deadband + magnitude * (config.max_duty - config.deadband);
+        duty.min(config.max_duty).copysign(self.command)
+    }
+}
+
+impl<M: Motor> Motor for ShapedMotor<M> {
+    type Error = M::Error;
+    // A NaN target is ignored and the command keeps ramping to the previous one.
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        if !speed.value().is_nan() {
+            self.command = self.step(speed.value());
+        }
+        self.motor.set_speed(Duty::new(self.output()))
+    }
+    // Stopping is never rate limited.
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.command = 0.;
+        self.motor.brake()
+    }
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.command = 0.;
+        self.motor.coast()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMotor, MotorState};
+
+    fn output(motor: &ShapedMotor<MockMotor>) -> f32 {
+        match motor.motor.state {
+            MotorState::Speed(duty) => duty.value(),
+            _ => panic!("motor not driven"),
+        }
+    }
+
+    #[test]
+    fn ignores_nan_target() {
+        let config = ShapingConfig {
+            max_step: 0.1,
+            ..Default::default()
+        };
+        let mut motor = ShapedMotor::new(MockMotor::default(), config);
+        motor.set_speed(Duty::new(0.5)).unwrap();
+        motor.set_speed(Duty::new(f32::NAN)).unwrap();
+        assert!((motor.command() - 0.1).abs() < 1e-6);
+        assert!((output(&motor) - 0.1).abs() < 1e-6);
+        motor.set_speed(Duty::new(0.5)).unwrap();
+        assert!((motor.command() - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn limits_step_and_soft_start() {
+        let config = ShapingConfig {
+            max_step: 0.2,
+            soft_start_step: 0.05,
+            soft_start_until: 0.1,
+            ..Default::default()
+        };
+        let mut motor = ShapedMotor::new(MockMotor::default(), config);
+        let commands = [0.05, 0.1, 0.3, 0.5, 0.7].map(|expected| {
+            motor.set_speed(Duty::MAX).unwrap();
+            (motor.command(), expected)
+        });
+        for (command, expected) in commands {
+            assert!((command - expected).abs() < 1e-6, "{command} != {expected}");
+        }
+    }
+
+    #[test]
+    fn compensates_deadband() {
+        let config = ShapingConfig {
+            deadband: 0.2,
+            min_duty: 0.05,
+            ..Default::default()
+        };
+        let mut motor = ShapedMotor::new(MockMotor::default(), config);
+        motor.set_speed(Duty::new(0.5)).unwrap();
+        assert!((output(&motor) - 0.6).abs() < 1e-6);
+        motor.set_speed(Duty::new(-0.5)).unwrap();
+        assert!((output(&motor) + 0.6).abs() < 1e-6);
+        motor.set_speed(Duty::new(0.01)).unwrap();
+        assert_eq!(output(&motor), 0.);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_negative_step() {
+        let config = ShapingConfig {
+            max_step: -0.1,
+            ..Default::default()
+        };
+        ShapedMotor::new(MockMotor::default(), config);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_nan_soft_start_step() {
+        let mut motor = ShapedMotor::new(MockMotor::default(), ShapingConfig::default());
+        motor.set_config(ShapingConfig {
+            soft_start_step: f32::NAN,
+            ..Default::default()
+        });
+    }
+}
@@ -1,44 +1,91 @@
+use core::convert::Infallible;
+
 #[allow(unused_imports)]
 use micromath::F32Ext;
 
+use crate::units::{Angle, AngularVelocity, Duty, Length, LinearVelocity};
+
 use super::motor::Motor;
 
 pub struct OmniWheel<M: Motor> {
     motor: M,
     vx: f32,
     vy: f32,
-    radius: f32,
+    radius: Length,
+    max_speed: LinearVelocity,
 }
 
 impl<M: Motor> OmniWheel<M> {
-    pub fn new(motor: M, angle: f32, radius: f32) -> Self {
-        let (vy, vx) = angle.sin_cos();
+    // `angle` is the direction the wheel drives in and `radius` its distance from
+    // the center of the robot. `max_speed` is the wheel's surface speed at full
+    // duty.
+    pub fn new(motor: M, angle: Angle, radius: Length, max_speed: LinearVelocity) -> Self {
+        let (vy, vx) = angle.radians().sin_cos();
         Self {
             motor,
             vx,
             vy,
             radius,
+            max_speed,
         }
     }
-    pub fn run(&mut self, x: f32, y: f32, rotation: f32) {
-        let output = self.vx * x + self.vy * y + self.radius * rotation;
-        if output >= 0. {
-            let duty = output.clamp(u16::MIN.into(), u16::MAX.into()).round() as u16;
-            self.motor.cw(duty);
-        } else {
-            let duty = output.abs().clamp(u16::MIN.into(), u16::MAX.into()).round() as u16;
-            self.motor.ccw(duty);
-        }
+    pub fn run(
+        &mut self,
+        x: LinearVelocity,
+        y: LinearVelocity,
+        rotation: AngularVelocity,
+    ) -> Result<(), M::Error> {
+        let speed = x * self.vx + y * self.vy + rotation * self.radius;
+        self.motor.set_speed(Duty::new(speed / self.max_speed))
+    }
+    pub fn brake(&mut self) -> Result<(), M::Error> {
+        self.motor.brake()
+    }
+    pub fn coast(&mut self) -> Result<(), M::Error> {
+        self.motor.coast()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WheelError<E> {
+    pub index: usize,
+    pub error: E,
+}
+
+impl<E: Into<Infallible>> From<WheelError<E>> for Infallible {
+    fn from(value: WheelError<E>) -> Self {
+        value.error.into()
     }
 }
 
 pub struct OmniWheels<M: Motor, const N: usize>([OmniWheel<M>; N]);
 
 impl<M: Motor, const N: usize> OmniWheels<M, N> {
-    pub fn run(&mut self, x: f32, y: f32, rotation: f32) {
-        for wheel in self.0.iter_mut() {
-            wheel.run(x, y, rotation);
+    pub fn run(
+        &mut self,
+        x: LinearVelocity,
+        y: LinearVelocity,
+        rotation: AngularVelocity,
+    ) -> Result<(), WheelError<M::Error>> {
+        self.for_each(|wheel| wheel.run(x, y, rotation))
+    }
+    pub fn brake(&mut self) -> Result<(), WheelError<M::Error>> {
+        self.for_each(OmniWheel::brake)
+    }
+    pub fn coast(&mut self) -> Result<(), WheelError<M::Error>> {
+        self.for_each(OmniWheel::coast)
+    }
+    fn for_each(
+        &mut self,
+        mut f: impl FnMut(&mut OmniWheel<M>) -> Result<(), M::Error>,
+    ) -> Result<(), WheelError<M::Error>> {
+        // Every wheel is commanded even if an earlier one fails, so that e.g. a
+        // brake isn't skipped on the remaining wheels. The first error is returned.
+        let mut result = Ok(());
+        for (index, wheel) in self.0.iter_mut().enumerate() {
+            result = result.and(f(wheel).map_err(|error| WheelError { index, error }));
         }
+        result
     }
 }
 
@@ -47,3 +94,62 @@ impl<M: Motor, const N: usize> From<[OmniWheel<M>; N]> for OmniWheels<M, N> {
         Self(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMotor, MockMotorError, MotorState};
+
+    fn wheels() -> OmniWheels<MockMotor, 3> {
+        let radius = Length::from_meters(0.2);
+        let max_speed = LinearVelocity::from_meters_per_second(2.);
+        OmniWheels::from([0., 120., 240.].map(|angle| {
+            OmniWheel::new(
+                MockMotor::default(),
+                Angle::from_degrees(angle),
+                radius,
+                max_speed,
+            )
+        }))
+    }
+
+    fn states(wheels: &OmniWheels<MockMotor, 3>) -> [MotorState; 3] {
+        wheels.0.each_ref().map(|wheel| wheel.motor.state)
+    }
+
+    #[test]
+    fn runs_every_wheel() {
+        let mut wheels = wheels();
+        let zero = LinearVelocity::ZERO;
+        wheels
+            .run(
+                LinearVelocity::from_meters_per_second(1.),
+                zero,
+                AngularVelocity::ZERO,
+            )
+            .unwrap();
+        let [MotorState::Speed(a), MotorState::Speed(b), MotorState::Speed(c)] = states(&wheels)
+        else {
+            panic!("wheel not driven");
+        };
+        assert!((a.value() - 0.5).abs() < 1e-6);
+        assert!((b.value() + 0.25).abs() < 1e-6);
+        assert!((c.value() + 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn commands_all_wheels_when_one_fails() {
+        let mut wheels = wheels();
+        wheels.0[0].motor.fail = true;
+        wheels.0[1].motor.fail = true;
+        assert_eq!(
+            wheels.brake(),
+            Err(WheelError {
+                index: 0,
+                error: MockMotorError
+            })
+        );
+        assert_eq!(states(&wheels)[2], MotorState::Brake);
+        assert!(wheels.0.iter().all(|wheel| wheel.motor.calls == 1));
+    }
+}
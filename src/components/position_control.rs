@@ -0,0 +1,384 @@
+use core::{convert::Infallible, time::Duration};
+
+use advanced_pid::{prelude::*, PidGain, VelPid};
+
+use crate::units::{Angle, AngularVelocity, Duty};
+
+use super::{
+    motion_profile::{MotionLimits, MotionProfile},
+    motor::Motor,
+    rotary_encoder::{Absolute, Incremental, MultiTurn},
+    velocity_control::velocity_pid_config,
+};
+
+// Continuous shaft position, counting every turn.
+pub trait PositionFeedback {
+    type Error;
+    fn position(&mut self) -> Result<Angle, Self::Error>;
+}
+
+pub struct IncrementalFeedback<E: Incremental>(pub E);
+
+impl<E: Incremental> PositionFeedback for IncrementalFeedback<E> {
+    type Error = Infallible;
+    fn position(&mut self) -> Result<Angle, Self::Error> {
+        Ok(self.0.angle())
+    }
+}
+
+impl<E: Absolute> PositionFeedback for MultiTurn<E> {
+    type Error = E::Error;
+    fn position(&mut self) -> Result<Angle, Self::Error> {
+        self.update()?;
+        Ok(self.angle())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PositionControlError<M, F> {
+    Motor(M),
+    Feedback(F),
+}
+
+impl<M: Into<Infallible>, F: Into<Infallible>> From<PositionControlError<M, F>> for Infallible {
+    fn from(value: PositionControlError<M, F>) -> Self {
+        match value {
+            PositionControlError::Motor(e) => e.into(),
+            PositionControlError::Feedback(e) => e.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ProfileKind {
+    #[default]
+    Trapezoidal,
+    SCurve,
+}
+
+// Gains work on radians: the velocity gains and feed-forwards are duty per
+// radian per second (squared).
+#[derive(Debug, Clone)]
+pub struct PositionControlConfig {
+    pub limits: MotionLimits,
+    pub profile: ProfileKind,
+    // Velocity correction per radian of position error (outer loop), in 1/s.
+    pub position_gain: f32,
+    // Duty per radian per second of velocity error (inner loop).
+    pub velocity_gain: PidGain,
+    pub velocity_feed_forward: f32,
+    pub acceleration_feed_forward: f32,
+    // The target counts as reached once the position stays within `tolerance` for
+    // `settle_time` after the profile has finished.
+    pub tolerance: Angle,
+    pub settle_time: Duration,
+    pub control_freq: f32,
+}
+
+impl PositionControlConfig {
+    fn is_valid(&self) -> bool {
+        self.limits.is_valid()
+            && self.control_freq > 0.
+            && self.control_freq.is_finite()
+            && self.tolerance >= Angle::ZERO
+    }
+}
+
+pub struct PositionControlledMotor<M: Motor, F: PositionFeedback> {
+    motor: M,
+    feedback: F,
+    config: PositionControlConfig,
+    velocity_pid: VelPid,
+    profile: MotionProfile,
+    elapsed: f32,
+    position: Angle,
+    velocity: AngularVelocity,
+    settled: Duration,
+    output: Duty,
+}
+
+impl<M: Motor, F: PositionFeedback> PositionControlledMotor<M, F> {
+    // Holds the current position until the first `move_to`.
+    pub fn new(motor: M, mut feedback: F, config: PositionControlConfig) -> Result<Self, F::Error> {
+        assert!(config.is_valid());
+        let position = feedback.position()?;
+        Ok(Self {
+            motor,
+            feedback,
+            velocity_pid: VelPid::new(velocity_pid_config(
+                &config.velocity_gain,
+                config.control_freq,
+            )),
+            profile: MotionProfile::trapezoidal(position, position, &config.limits),
+            config,
+            elapsed: 0.,
+            position,
+            velocity: AngularVelocity::ZERO,
+            settled: Duration::ZERO,
+            output: Duty::ZERO,
+        })
+    }
+    pub fn set_config(&mut self, config: PositionControlConfig) {
+        assert!(config.is_valid());
+        self.velocity_pid.reset_config(velocity_pid_config(
+            &config.velocity_gain,
+            config.control_freq,
+        ));
+        self.config = config;
+    }
+    pub fn period(&self) -> Duration {
+        Duration::from_secs_f32(1. / self.config.control_freq)
+    }
+    // Profiles are planned from standstill at the current position.
+    pub fn move_to(&mut self, target: Angle) {
+        let start = self.position;
+        self.profile = match self.config.profile {
+            ProfileKind::Trapezoidal => {
+                MotionProfile::trapezoidal(start, target, &self.config.limits)
+            }
+            ProfileKind::SCurve => MotionProfile::s_curve(start, target, &self.config.limits),
+        };
+        self.elapsed = 0.;
+        self.settled = Duration::ZERO;
+    }
+    pub fn target(&self) -> Angle {
+        self.profile.target()
+    }
+    pub fn position(&self) -> Angle {
+        self.position
+    }
+    pub fn velocity(&self) -> AngularVelocity {
+        self.velocity
+    }
+    pub fn output(&self) -> Duty {
+        self.output
+    }
+    pub fn is_target_reached(&self) -> bool {
+        self.settled >= self.config.settle_time
+            && self.elapsed >= self.profile.duration()
+            && (self.profile.target() - self.position).abs() <= self.config.tolerance
+    }
+    pub fn update(&mut self) -> Result<(), PositionControlError<M::Error, F::Error>> {
+        let dt = 1. / self.config.control_freq;
+        let position = self
+            .feedback
+            .position()
+            .map_err(PositionControlError::Feedback)?;
+        self.velocity =
+            AngularVelocity::from_radians_per_second((position - self.position).radians() / dt);
+        self.position = position;
+        self.elapsed += dt;
+
+        let reference = self.profile.sample(self.elapsed);
+        let velocity_command = reference.velocity.radians_per_second()
+            + self.config.position_gain * (reference.position - position).radians();
+        let feedback =
+            self.velocity_pid
+                .update(velocity_command, self.velocity.radians_per_second(), dt);
+        self.output = Duty::new(
+            self.config.velocity_feed_forward * velocity_command
+                + self.config.acceleration_feed_forward
+                    * reference.acceleration.radians_per_second_squared()
+                + feedback,
+        );
+
+        let finished = self.elapsed >= self.profile.duration();
+        if finished && (self.profile.target() - position).abs() <= self.config.tolerance {
+            self.settled += self.period();
+        } else {
+            self.settled = Duration::ZERO;
+        }
+
+        self.motor
+            .set_speed(self.output)
+            .map_err(PositionControlError::Motor)
+    }
+    // Stops at the current position and holds it.
+    pub fn stop(&mut self) -> Result<(), M::Error> {
+        self.profile =
+            MotionProfile::trapezoidal(self.position, self.position, &self.config.limits);
+        self.elapsed = 0.;
+        self.settled = Duration::ZERO;
+        self.output = Duty::ZERO;
+        self.velocity_pid.reset_config(velocity_pid_config(
+            &self.config.velocity_gain,
+            self.config.control_freq,
+        ));
+        self.motor.brake()
+    }
+    pub fn into_inner(self) -> (M, F) {
+        (self.motor, self.feedback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::TAU;
+
+    use super::*;
+    use crate::{
+        mock::{MockEncoder, MockMotor, MotorState},
+        units::AngularAcceleration,
+    };
+
+    const RESOLUTION: u32 = 4096;
+    // Speed of the simulated motor at full duty, in rotations per second.
+    const PLANT_MAX_SPEED: f32 = 5.;
+
+    type Controller = PositionControlledMotor<MockMotor, IncrementalFeedback<MockEncoder>>;
+
+    fn config() -> PositionControlConfig {
+        PositionControlConfig {
+            limits: MotionLimits {
+                max_velocity: AngularVelocity::from_rotations_per_second(2.),
+                max_acceleration: AngularAcceleration::from_rotations_per_second_squared(8.),
+                max_jerk: 80. * TAU,
+            },
+            profile: ProfileKind::Trapezoidal,
+            position_gain: 10.,
+            velocity_gain: PidGain {
+                kp: 0.05 / TAU,
+                ki: 0.5 / TAU,
+                kd: 0.,
+            },
+            velocity_feed_forward: 1. / (PLANT_MAX_SPEED * TAU),
+            acceleration_feed_forward: 0.,
+            tolerance: Angle::from_rotations(0.01),
+            settle_time: Duration::from_millis(50),
+            control_freq: 1000.,
+        }
+    }
+
+    fn controller(config: PositionControlConfig) -> Controller {
+        let feedback = IncrementalFeedback(MockEncoder::new(RESOLUTION));
+        PositionControlledMotor::new(MockMotor::default(), feedback, config).unwrap()
+    }
+
+    fn rot(rotations: f32) -> Angle {
+        Angle::from_rotations(rotations)
+    }
+
+    // Distance from the position to `rotations`, in rotations.
+    fn error(controller: &Controller, rotations: f32) -> f32 {
+        (controller.position().rotations() - rotations).abs()
+    }
+
+    // Turns the motor at a speed proportional to its duty for one period, then
+    // runs the controller.
+    fn step(controller: &mut Controller) {
+        let duty = match controller.motor.state {
+            MotorState::Speed(duty) => duty.value(),
+            _ => 0.,
+        };
+        let dt = 1. / controller.config.control_freq;
+        let moved = duty * PLANT_MAX_SPEED * dt * RESOLUTION as f32;
+        controller.feedback.0.position += moved.round() as i64;
+        controller.update().unwrap();
+    }
+
+    // Steps until the target is reached and returns the time it took.
+    fn run(controller: &mut Controller, max_time: f32) -> f32 {
+        let dt = 1. / controller.config.control_freq;
+        for i in 1..=(max_time / dt) as usize {
+            step(controller);
+            if controller.is_target_reached() {
+                return i as f32 * dt;
+            }
+        }
+        panic!("target not reached, at {:?}", controller.position());
+    }
+
+    #[test]
+    fn follows_profiles_to_target() {
+        for profile in [ProfileKind::Trapezoidal, ProfileKind::SCurve] {
+            let mut controller = controller(PositionControlConfig {
+                profile,
+                ..config()
+            });
+            controller.move_to(rot(3.));
+            assert_eq!(controller.target(), rot(3.));
+            let duration = controller.profile.duration();
+            let time = run(&mut controller, 5.);
+            assert!(error(&controller, 3.) <= 0.01);
+            // Reached only after the profile and the settle time have passed.
+            assert!(time >= duration + 0.05 - 1e-3, "{time} < {duration}");
+            assert!(time < duration + 0.5, "{time} too slow");
+            // The velocity stays near the limit on the way.
+            controller.move_to(rot(0.));
+            for _ in 0..1000 {
+                step(&mut controller);
+                assert!(controller.velocity().rotations_per_second().abs() < 2.5);
+            }
+        }
+    }
+
+    #[test]
+    fn waits_for_settle_time() {
+        let mut controller = controller(config());
+        controller.move_to(rot(0.5));
+        run(&mut controller, 5.);
+        // Pushing the shaft out of the tolerance restarts the settle time.
+        controller.feedback.0.position += (0.1 * RESOLUTION as f32) as i64;
+        step(&mut controller);
+        assert!(!controller.is_target_reached());
+        let time = run(&mut controller, 5.);
+        assert!(time >= 0.05 - 1e-3);
+        assert!(error(&controller, 0.5) <= 0.01);
+    }
+
+    #[test]
+    fn retargets_while_moving() {
+        let mut controller = controller(config());
+        controller.move_to(rot(5.));
+        for _ in 0..500 {
+            step(&mut controller);
+        }
+        assert!(controller.position() > rot(0.5));
+        controller.move_to(rot(-1.));
+        assert!(!controller.is_target_reached());
+        run(&mut controller, 10.);
+        assert!(error(&controller, -1.) <= 0.01);
+    }
+
+    #[test]
+    fn stop_holds_position() {
+        let mut controller = controller(config());
+        controller.move_to(rot(1.));
+        run(&mut controller, 5.);
+        controller.stop().unwrap();
+        assert_eq!(controller.motor.state, MotorState::Brake);
+        assert_eq!(controller.output(), Duty::ZERO);
+        // The settle time starts over at the new target.
+        assert!(!controller.is_target_reached());
+
+        controller.move_to(rot(4.));
+        for _ in 0..300 {
+            step(&mut controller);
+        }
+        controller.stop().unwrap();
+        let stopped = controller.position();
+        assert_eq!(controller.target(), stopped);
+        assert!(!controller.is_target_reached());
+        run(&mut controller, 5.);
+        assert!(error(&controller, stopped.rotations()) < 0.5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_control_freq() {
+        controller(PositionControlConfig {
+            control_freq: 0.,
+            ..config()
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_nan_limits() {
+        let mut controller = controller(config());
+        let mut config = config();
+        config.limits.max_acceleration =
+            AngularAcceleration::from_radians_per_second_squared(f32::NAN);
+        controller.set_config(config);
+    }
+}
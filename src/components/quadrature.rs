@@ -0,0 +1,321 @@
+use core::convert::Infallible;
+
+use embedded_hal::digital::{ErrorType, InputPin};
+
+use super::rotary_encoder::{Incremental, RotaryEncoder};
+
+// Which edges are counted: rising edges of A, every edge of A, or every edge of
+// both channels.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum QuadratureMode {
+    X1,
+    X2,
+    #[default]
+    X4,
+}
+
+impl QuadratureMode {
+    fn multiplier(&self) -> u32 {
+        match self {
+            QuadratureMode::X1 => 1,
+            QuadratureMode::X2 => 2,
+            QuadratureMode::X4 => 4,
+        }
+    }
+}
+
+// Stands in for the index pin of an encoder without one.
+pub struct NoIndex;
+
+impl ErrorType for NoIndex {
+    type Error = Infallible;
+}
+
+impl InputPin for NoIndex {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QuadratureError<A, B, Z> {
+    A(A),
+    B(B),
+    Index(Z),
+}
+
+impl From<QuadratureError<Infallible, Infallible, Infallible>> for Infallible {
+    fn from(value: QuadratureError<Infallible, Infallible, Infallible>) -> Self {
+        match value {
+            QuadratureError::A(e) | QuadratureError::B(e) | QuadratureError::Index(e) => e,
+        }
+    }
+}
+
+type PinError<A, B, Z> =
+    QuadratureError<<A as ErrorType>::Error, <B as ErrorType>::Error, <Z as ErrorType>::Error>;
+
+// States are (A << 1) | B. Going forward, A leads B: 00, 10, 11, 01.
+fn transition(previous: u8, current: u8) -> Option<i8> {
+    match (previous, current) {
+        (0b00, 0b10) | (0b10, 0b11) | (0b11, 0b01) | (0b01, 0b00) => Some(1),
+        (0b10, 0b00) | (0b11, 0b10) | (0b01, 0b11) | (0b00, 0b01) => Some(-1),
+        (previous, current) if previous == current => Some(0),
+        // Both channels changed at once, so an edge was missed.
+        _ => None,
+    }
+}
+
+// Decodes A/B quadrature signals in software. `update` must be called on every
+// edge, e.g. from a pin-change interrupt, or from a timer faster than the
+// shortest time between two edges.
+pub struct QuadratureDecoder<A: InputPin, B: InputPin, Z: InputPin = NoIndex> {
+    a: A,
+    b: B,
+    index: Z,
+    mode: QuadratureMode,
+    // Lines (full cycles) per revolution.
+    lines: u32,
+    state: u8,
+    index_high: bool,
+    zero_on_index: bool,
+    position: i64,
+    index_position: Option<i64>,
+    illegal_transitions: u32,
+}
+
+impl<A: InputPin, B: InputPin> QuadratureDecoder<A, B> {
+    pub fn new(
+        a: A,
+        b: B,
+        lines: u32,
+        mode: QuadratureMode,
+    ) -> Result<Self, PinError<A, B, NoIndex>> {
+        QuadratureDecoder::with_index(a, b, NoIndex, lines, mode, false)
+    }
+}
+
+impl<A: InputPin, B: InputPin, Z: InputPin> QuadratureDecoder<A, B, Z> {
+    // With `zero_on_index` the position is reset on every rising edge of Z.
+    pub fn with_index(
+        mut a: A,
+        mut b: B,
+        mut index: Z,
+        lines: u32,
+        mode: QuadratureMode,
+        zero_on_index: bool,
+    ) -> Result<Self, PinError<A, B, Z>> {
+        let state = read_state(&mut a, &mut b)?;
+        let index_high = index.is_high().map_err(QuadratureError::Index)?;
+        Ok(Self {
+            a,
+            b,
+            index,
+            mode,
+            lines,
+            state,
+            index_high,
+            zero_on_index,
+            position: 0,
+            index_position: None,
+            illegal_transitions: 0,
+        })
+    }
+    pub fn illegal_transitions(&self) -> u32 {
+        self.illegal_transitions
+    }
+    // Position at the last index pulse, before any re-zeroing.
+    pub fn index_position(&self) -> Option<i64> {
+        self.index_position
+    }
+    pub fn into_inner(self) -> (A, B, Z) {
+        (self.a, self.b, self.index)
+    }
+    pub fn update(&mut self) -> Result<(), PinError<A, B, Z>> {
+        let state = read_state(&mut self.a, &mut self.b)?;
+        let previous = core::mem::replace(&mut self.state, state);
+        match transition(previous, state) {
+            Some(delta) => {
+                let a_changed = (previous ^ state) & 0b10 != 0;
+                let a_rising = previous & 0b10 == 0 && state & 0b10 != 0;
+                let a_falling = previous & 0b10 != 0 && state & 0b10 == 0;
+                let counted = match self.mode {
+                    QuadratureMode::X4 => true,
+                    QuadratureMode::X2 => a_changed,
+                    // A rising forwards is A falling backwards.
+                    QuadratureMode::X1 => (delta > 0 && a_rising) || (delta < 0 && a_falling),
+                };
+                if counted {
+                    self.position += delta as i64;
+                }
+            }
+            None => self.illegal_transitions = self.illegal_transitions.wrapping_add(1),
+        }
+
+        let index_high = self.index.is_high().map_err(QuadratureError::Index)?;
+        if index_high && !self.index_high {
+            self.index_position = Some(self.position);
+            if self.zero_on_index {
+                self.position = 0;
+            }
+        }
+        self.index_high = index_high;
+        Ok(())
+    }
+}
+
+fn read_state<A: InputPin, B: InputPin, Z>(
+    a: &mut A,
+    b: &mut B,
+) -> Result<u8, QuadratureError<A::Error, B::Error, Z>> {
+    let a = a.is_high().map_err(QuadratureError::A)?;
+    let b = b.is_high().map_err(QuadratureError::B)?;
+    Ok((a as u8) << 1 | b as u8)
+}
+
+impl<A: InputPin, B: InputPin, Z: InputPin> RotaryEncoder for QuadratureDecoder<A, B, Z> {
+    fn resolution(&self) -> u32 {
+        self.lines * self.mode.multiplier()
+    }
+}
+
+impl<A: InputPin, B: InputPin, Z: InputPin> Incremental for QuadratureDecoder<A, B, Z> {
+    fn position(&mut self) -> i64 {
+        self.position
+    }
+    fn set_position(&mut self, position: i64) {
+        self.position = position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockPin;
+
+    type Decoder = QuadratureDecoder<MockPin, MockPin, MockPin>;
+
+    const FORWARD: [u8; 4] = [0b10, 0b11, 0b01, 0b00];
+
+    fn decoder(mode: QuadratureMode, zero_on_index: bool) -> Decoder {
+        let pin = || MockPin { high: false };
+        QuadratureDecoder::with_index(pin(), pin(), pin(), 100, mode, zero_on_index).unwrap()
+    }
+
+    fn set(decoder: &mut Decoder, state: u8) {
+        decoder.a.high = state & 0b10 != 0;
+        decoder.b.high = state & 0b01 != 0;
+        decoder.update().unwrap();
+    }
+
+    // Runs `cycles` full cycles, backwards for negative ones.
+    fn turn(decoder: &mut Decoder, cycles: i32) {
+        for _ in 0..cycles.abs() {
+            if cycles > 0 {
+                FORWARD.into_iter().for_each(|state| set(decoder, state));
+            } else {
+                FORWARD
+                    .into_iter()
+                    .rev()
+                    .skip(1)
+                    .for_each(|state| set(decoder, state));
+                set(decoder, 0b00);
+            }
+        }
+    }
+
+    #[test]
+    fn counts_edges_per_mode() {
+        for (mode, per_cycle) in [
+            (QuadratureMode::X1, 1),
+            (QuadratureMode::X2, 2),
+            (QuadratureMode::X4, 4),
+        ] {
+            let mut decoder = decoder(mode, false);
+            assert_eq!(decoder.resolution(), 100 * per_cycle);
+            turn(&mut decoder, 3);
+            assert_eq!(decoder.position(), 3 * per_cycle as i64);
+            turn(&mut decoder, -5);
+            assert_eq!(decoder.position(), -2 * per_cycle as i64);
+            assert_eq!(decoder.illegal_transitions(), 0);
+        }
+    }
+
+    #[test]
+    fn x1_counts_once_per_cycle_when_reversing() {
+        let mut decoder = decoder(QuadratureMode::X1, false);
+        // A rises, then the shaft jitters back and forth over that edge.
+        set(&mut decoder, 0b10);
+        assert_eq!(decoder.position(), 1);
+        set(&mut decoder, 0b00);
+        assert_eq!(decoder.position(), 0);
+        set(&mut decoder, 0b10);
+        set(&mut decoder, 0b11);
+        set(&mut decoder, 0b10);
+        assert_eq!(decoder.position(), 1);
+    }
+
+    #[test]
+    fn counts_invalid_transitions() {
+        let mut decoder = decoder(QuadratureMode::X4, false);
+        set(&mut decoder, 0b10);
+        // Both channels change at once.
+        set(&mut decoder, 0b01);
+        assert_eq!(decoder.illegal_transitions(), 1);
+        assert_eq!(decoder.position(), 1);
+        set(&mut decoder, 0b10);
+        assert_eq!(decoder.illegal_transitions(), 2);
+        // Decoding carries on from the new state.
+        set(&mut decoder, 0b11);
+        assert_eq!(decoder.position(), 2);
+        // Repeated updates without an edge count nothing.
+        set(&mut decoder, 0b11);
+        assert_eq!(decoder.position(), 2);
+    }
+
+    #[test]
+    fn latches_index_pulse() {
+        let mut decoder = decoder(QuadratureMode::X4, false);
+        turn(&mut decoder, 2);
+        assert_eq!(decoder.index_position(), None);
+        decoder.index.high = true;
+        set(&mut decoder, 0b10);
+        assert_eq!(decoder.index_position(), Some(9));
+        // Only the rising edge counts.
+        set(&mut decoder, 0b11);
+        assert_eq!(decoder.index_position(), Some(9));
+        assert_eq!(decoder.position(), 10);
+    }
+
+    #[test]
+    fn zeroes_on_index_pulse() {
+        let mut decoder = decoder(QuadratureMode::X4, true);
+        turn(&mut decoder, -3);
+        decoder.index.high = true;
+        set(&mut decoder, 0b01);
+        assert_eq!(decoder.index_position(), Some(-13));
+        assert_eq!(decoder.position(), 0);
+        decoder.index.high = false;
+        turn(&mut decoder, -1);
+        set(&mut decoder, 0b01);
+        decoder.index.high = true;
+        set(&mut decoder, 0b11);
+        assert_eq!(decoder.index_position(), Some(-5));
+        assert_eq!(decoder.position(), 0);
+    }
+
+    #[test]
+    fn works_without_index() {
+        let pin = || MockPin { high: true };
+        let mut decoder = QuadratureDecoder::new(pin(), pin(), 10, QuadratureMode::X2).unwrap();
+        decoder.a.high = false;
+        decoder.update().unwrap();
+        assert_eq!(decoder.position(), 1);
+        assert_eq!(decoder.index_position(), None);
+        decoder.set_position(40);
+        assert!((decoder.rotations() - 2.).abs() < 1e-6);
+    }
+}
@@ -1,28 +1,362 @@
-use core::time::Duration;
-
-use super::motor::Dir;
+use crate::units::Angle;
 
 pub trait RotaryEncoder {
     fn resolution(&self) -> u32;
 }
 
+// Positions are signed ticks since the last reset, continuous across hardware
+// counter wrap-arounds. `resolution` is in ticks per revolution.
 pub trait Incremental: RotaryEncoder {
-    fn get_count(&self) -> u32;
-    fn get_dir(&self) -> Dir;
-    fn reset_count(&mut self);
+    fn position(&mut self) -> i64;
+    fn set_position(&mut self, position: i64);
 
-    fn rotations(&self) -> f32 {
-        if self.get_dir() == Dir::Cw {
-            self.get_count() as f32 / self.resolution() as f32
-        } else {
-            -1. * self.get_count() as f32 / self.resolution() as f32
-        }
+    fn reset_position(&mut self) {
+        self.set_position(0);
+    }
+    fn rotations(&mut self) -> f32 {
+        self.position() as f32 / self.resolution() as f32
     }
-    fn rpm(&self, dt: Duration) -> f32 {
-        self.rotations() / dt.as_secs_f32() * 60.
+    fn angle(&mut self) -> Angle {
+        Angle::from_rotations(self.rotations())
     }
 }
 
+// Positions are in ticks within a single revolution, 0..resolution.
 pub trait Absolute: RotaryEncoder {
-    fn get_position(&self) -> u32;
+    type Error;
+    fn get_position(&mut self) -> Result<u32, Self::Error>;
+
+    fn get_angle(&mut self) -> Result<Angle, Self::Error> {
+        let position = self.get_position()?;
+        Ok(Angle::from_rotations(
+            position as f32 / self.resolution() as f32,
+        ))
+    }
+}
+
+// Moves the zero of an absolute encoder, e.g. to a mechanical reference.
+pub struct ZeroOffset<E: Absolute> {
+    encoder: E,
+    offset: u32,
+}
+
+impl<E: Absolute> ZeroOffset<E> {
+    pub fn new(encoder: E, offset: u32) -> Self {
+        Self { encoder, offset }
+    }
+    // Makes the current position the zero.
+    pub fn calibrate(&mut self) -> Result<(), E::Error> {
+        self.offset = self.encoder.get_position()?;
+        Ok(())
+    }
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+    pub fn into_inner(self) -> E {
+        self.encoder
+    }
+}
+
+impl<E: Absolute> RotaryEncoder for ZeroOffset<E> {
+    fn resolution(&self) -> u32 {
+        self.encoder.resolution()
+    }
+}
+
+impl<E: Absolute> Absolute for ZeroOffset<E> {
+    type Error = E::Error;
+    fn get_position(&mut self) -> Result<u32, Self::Error> {
+        let resolution = self.encoder.resolution();
+        let position = self.encoder.get_position()?;
+        Ok((position + resolution - self.offset % resolution) % resolution)
+    }
+}
+
+// Counts the turns of a single-turn absolute encoder to get a continuous
+// position. It has to be updated at least twice per revolution so the direction
+// of a wrap-around can be told.
+pub struct MultiTurn<E: Absolute> {
+    encoder: E,
+    last: Option<u32>,
+    position: i64,
+}
+
+impl<E: Absolute> MultiTurn<E> {
+    pub fn new(encoder: E) -> Self {
+        Self {
+            encoder,
+            last: None,
+            position: 0,
+        }
+    }
+    // Continuous position in ticks as of the last `update`.
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+    pub fn rotations(&self) -> f32 {
+        self.position as f32 / self.encoder.resolution() as f32
+    }
+    pub fn angle(&self) -> Angle {
+        Angle::from_rotations(self.rotations())
+    }
+    // Keeps the angle within the revolution and sets the number of turns.
+    pub fn set_turns(&mut self, turns: i64) {
+        let resolution = self.encoder.resolution() as i64;
+        self.position = turns * resolution + self.position.rem_euclid(resolution);
+    }
+    pub fn update(&mut self) -> Result<i64, E::Error> {
+        let resolution = self.encoder.resolution() as i64;
+        let raw = self.encoder.get_position()? as i64;
+        self.position = match self.last {
+            None => raw,
+            Some(last) => {
+                // The shortest way from the last angle to the new one.
+                let delta = (raw - last as i64).rem_euclid(resolution);
+                let delta = if delta > resolution / 2 {
+                    delta - resolution
+                } else {
+                    delta
+                };
+                self.position + delta
+            }
+        };
+        self.last = Some(raw as u32);
+        Ok(self.position)
+    }
+    pub fn into_inner(self) -> E {
+        self.encoder
+    }
+}
+
+// Extends a counter of `bits` bits (1..=32) into a continuous position. It has to
+// be updated at least once per half range of the counter so the direction of a
+// wrap-around can be told.
+#[derive(Debug, Clone, Copy)]
+pub struct CounterUnwrapper {
+    bits: u32,
+    last: u32,
+    position: i64,
+}
+
+impl CounterUnwrapper {
+    pub fn new(bits: u32, count: u32) -> Self {
+        assert!((1..=32).contains(&bits));
+        Self {
+            bits,
+            last: count,
+            position: 0,
+        }
+    }
+    pub fn for_u16(count: u16) -> Self {
+        Self::new(16, count.into())
+    }
+    pub fn for_u32(count: u32) -> Self {
+        Self::new(32, count)
+    }
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+    pub fn set_position(&mut self, position: i64) {
+        self.position = position;
+    }
+    pub fn update(&mut self, count: u32) -> i64 {
+        let shift = 32 - self.bits;
+        // Sign-extends the wrapped difference from the counter's width.
+        let delta = (count.wrapping_sub(self.last) << shift) as i32 >> shift;
+        self.last = count;
+        self.position += delta as i64;
+        self.position
+    }
+}
+
+// A hardware quadrature or pulse counter, e.g. a timer in encoder mode.
+pub trait Counter {
+    const BITS: u32;
+    fn count(&mut self) -> u32;
+}
+
+pub struct CounterEncoder<C: Counter> {
+    counter: C,
+    unwrapper: CounterUnwrapper,
+    resolution: u32,
+}
+
+impl<C: Counter> CounterEncoder<C> {
+    pub fn new(mut counter: C, resolution: u32) -> Self {
+        let count = counter.count();
+        Self {
+            counter,
+            unwrapper: CounterUnwrapper::new(C::BITS, count),
+            resolution,
+        }
+    }
+    pub fn into_inner(self) -> C {
+        self.counter
+    }
+}
+
+impl<C: Counter> RotaryEncoder for CounterEncoder<C> {
+    fn resolution(&self) -> u32 {
+        self.resolution
+    }
+}
+
+impl<C: Counter> Incremental for CounterEncoder<C> {
+    fn position(&mut self) -> i64 {
+        let count = self.counter.count();
+        self.unwrapper.update(count)
+    }
+    fn set_position(&mut self, position: i64) {
+        let count = self.counter.count();
+        self.unwrapper.update(count);
+        self.unwrapper.set_position(position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockAbsoluteEncoder;
+
+    #[test]
+    fn unwraps_u16_in_both_directions() {
+        let mut unwrapper = CounterUnwrapper::for_u16(65530);
+        assert_eq!(unwrapper.update(65535), 5);
+        assert_eq!(unwrapper.update(4), 10);
+        assert_eq!(unwrapper.update(65534), 4);
+        assert_eq!(unwrapper.update(65500), -30);
+    }
+
+    #[test]
+    fn unwraps_u32() {
+        let mut unwrapper = CounterUnwrapper::for_u32(u32::MAX - 1);
+        assert_eq!(unwrapper.update(3), 5);
+        assert_eq!(unwrapper.update(u32::MAX), 1);
+        // Keeps counting past the range of the counter.
+        let mut position = 1;
+        for count in [0x4000_0000u32, 0x8000_0000, 0xC000_0000, 0, 0x4000_0000] {
+            let expected = position + count.wrapping_sub(unwrapper.last) as i64;
+            position = unwrapper.update(count);
+            assert_eq!(position, expected);
+        }
+        assert_eq!(position, (1i64 << 32) + (1 << 30) + 2);
+    }
+
+    #[test]
+    fn unwraps_narrow_counters() {
+        // A 12-bit counter whose upper bits are garbage.
+        let mut unwrapper = CounterUnwrapper::new(12, 4090);
+        assert_eq!(unwrapper.update(0xF000 | 5), 11);
+        assert_eq!(unwrapper.update(4000), -90);
+
+        let mut unwrapper = CounterUnwrapper::new(1, 0);
+        assert_eq!(unwrapper.update(1), -1);
+        assert_eq!(unwrapper.update(0), -2);
+    }
+
+    #[test]
+    fn keeps_position_when_set() {
+        let mut unwrapper = CounterUnwrapper::for_u16(100);
+        unwrapper.update(200);
+        unwrapper.set_position(-5);
+        assert_eq!(unwrapper.update(150), -55);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_bits() {
+        CounterUnwrapper::new(0, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_wide_counters() {
+        CounterUnwrapper::new(33, 0);
+    }
+
+    struct Timer(u32);
+
+    impl Counter for Timer {
+        const BITS: u32 = 16;
+        fn count(&mut self) -> u32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn counter_encoder_starts_at_zero() {
+        let mut encoder = CounterEncoder::new(Timer(65000), 4096);
+        assert_eq!(encoder.position(), 0);
+        encoder.counter.0 = 1000;
+        assert_eq!(encoder.position(), 1536);
+        encoder.set_position(0);
+        encoder.counter.0 = 0;
+        assert_eq!(encoder.position(), -1000);
+        assert!((encoder.rotations() + 1000. / 4096.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn offsets_zero() {
+        let mut encoder = ZeroOffset::new(MockAbsoluteEncoder::new(4096), 1000);
+        for (position, expected) in [(1000, 0), (4095, 3095), (0, 3096), (999, 4095)] {
+            encoder.encoder.position = position;
+            assert_eq!(encoder.get_position(), Ok(expected));
+        }
+        // Offsets beyond one revolution wrap around.
+        let mut encoder = ZeroOffset::new(MockAbsoluteEncoder::new(4096), 1000 + 4096);
+        encoder.encoder.position = 1000;
+        assert_eq!(encoder.get_position(), Ok(0));
+    }
+
+    #[test]
+    fn calibrates_zero() {
+        let mut encoder = ZeroOffset::new(MockAbsoluteEncoder::new(4096), 0);
+        encoder.encoder.position = 3000;
+        encoder.calibrate().unwrap();
+        assert_eq!(encoder.offset(), 3000);
+        assert_eq!(encoder.get_position(), Ok(0));
+        encoder.encoder.position = 1976;
+        assert!((encoder.get_angle().unwrap().rotations() - 0.75).abs() < 1e-6);
+    }
+
+    #[test]
+    fn counts_turns_in_both_directions() {
+        let mut encoder = MultiTurn::new(MockAbsoluteEncoder::new(4096));
+        encoder.encoder.position = 3000;
+        assert_eq!(encoder.update(), Ok(3000));
+        // Forwards across zero.
+        for (position, expected) in [(4000, 4000), (100, 4196), (2100, 6196), (0, 8192)] {
+            encoder.encoder.position = position;
+            assert_eq!(encoder.update(), Ok(expected));
+        }
+        // And backwards past the start.
+        for (position, expected) in [
+            (3000, 7096),
+            (1000, 5096),
+            (4000, 4000),
+            (2000, 2000),
+            (0, 0),
+        ] {
+            encoder.encoder.position = position;
+            assert_eq!(encoder.update(), Ok(expected));
+        }
+        for (position, expected) in [(3000, -1096), (1500, -2596), (0, -4096), (3000, -5192)] {
+            encoder.encoder.position = position;
+            assert_eq!(encoder.update(), Ok(expected));
+        }
+        assert!((encoder.rotations() + 5192. / 4096.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sets_turns_within_revolution() {
+        let mut encoder = MultiTurn::new(MockAbsoluteEncoder::new(4096));
+        encoder.encoder.position = 1024;
+        encoder.update().unwrap();
+        encoder.set_turns(-2);
+        assert_eq!(encoder.position(), -2 * 4096 + 1024);
+        encoder.encoder.position = 3072;
+        assert_eq!(encoder.update(), Ok(-2 * 4096 + 3072));
+        encoder.set_turns(3);
+        assert_eq!(encoder.position(), 3 * 4096 + 3072);
+    }
 }
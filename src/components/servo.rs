@@ -0,0 +1,296 @@
+use core::time::Duration;
+
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::units::{Angle, AngularVelocity, Duty};
+
+use super::motor::{finite_speed, Motor};
+
+// Pulse widths are for a PWM running at 1 / `period`. Angles are relative to
+// the center position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServoConfig {
+    pub period: Duration,
+    pub min_pulse: Duration,
+    pub max_pulse: Duration,
+    // Travel between `min_pulse` and `max_pulse`.
+    pub range: Angle,
+    // Microseconds added to every pulse to correct an off-center horn or a
+    // drifting continuous-rotation servo.
+    pub center_trim: f32,
+    pub inverted: bool,
+    // Used by `Servo::update`. Must not be negative; infinity moves at once.
+    pub max_speed: AngularVelocity,
+}
+
+impl Default for ServoConfig {
+    // A typical 180° hobby servo at 50 Hz.
+    fn default() -> Self {
+        Self {
+            period: Duration::from_millis(20),
+            min_pulse: Duration::from_micros(500),
+            max_pulse: Duration::from_micros(2500),
+            range: Angle::from_degrees(180.),
+            center_trim: 0.,
+            inverted: false,
+            max_speed: AngularVelocity::from_radians_per_second(f32::INFINITY),
+        }
+    }
+}
+
+impl ServoConfig {
+    // Duty for a position in -1..=1 between the min and max pulse.
+    fn duty(&self, position: f32, max_duty: u16) -> u16 {
+        let position = if self.inverted { -position } else { position };
+        let min = self.min_pulse.as_secs_f32();
+        let max = self.max_pulse.as_secs_f32();
+        let pulse = (min + max) / 2. + position.clamp(-1., 1.) * (max - min) / 2.;
+        let pulse = pulse + self.center_trim * 1e-6;
+        let duty = pulse / self.period.as_secs_f32() * max_duty as f32;
+        duty.clamp(0., max_duty as f32) as u16
+    }
+}
+
+pub struct Servo<PWM: SetDutyCycle> {
+    pwm: PWM,
+    config: ServoConfig,
+    angle: Angle,
+    target: Angle,
+}
+
+impl<PWM: SetDutyCycle> Servo<PWM> {
+    pub fn new(pwm: PWM, config: ServoConfig) -> Self {
+        assert!(config.max_speed >= AngularVelocity::ZERO);
+        Self {
+            pwm,
+            config,
+            angle: Angle::ZERO,
+            target: Angle::ZERO,
+        }
+    }
+    pub fn set_config(&mut self, config: ServoConfig) {
+        assert!(config.max_speed >= AngularVelocity::ZERO);
+        self.config = config;
+    }
+    pub fn angle(&self) -> Angle {
+        self.angle
+    }
+    pub fn target(&self) -> Angle {
+        self.target
+    }
+    pub fn is_target_reached(&self) -> bool {
+        self.angle == self.target
+    }
+    // Moves to `angle` at once, ignoring `max_speed`.
+    pub fn set_angle(&mut self, angle: Angle) -> Result<(), PWM::Error> {
+        self.target = self.clamp(angle);
+        self.angle = self.target;
+        self.write()
+    }
+    // Starts a move limited to `max_speed`, carried out by `update`.
+    pub fn move_to(&mut self, angle: Angle) {
+        self.target = self.clamp(angle);
+    }
+    // Advances a move started with `move_to` by `dt`.
+    pub fn update(&mut self, dt: Duration) -> Result<(), PWM::Error> {
+        let error = self.target - self.angle;
+        let max_step = self.config.max_speed * dt;
+        // An infinite speed is checked first, as it gives a NaN step when `dt` is 0.
+        self.angle = if self.config.max_speed.radians_per_second().is_infinite()
+            || error.abs() <= max_step
+        {
+            self.target
+        } else {
+            self.angle + error.clamp(-max_step, max_step)
+        };
+        self.write()
+    }
+    // Stops driving the servo so it can be moved by hand.
+    pub fn release(&mut self) -> Result<(), PWM::Error> {
+        self.pwm.set_duty_cycle_fully_off()
+    }
+    pub fn into_inner(self) -> PWM {
+        self.pwm
+    }
+    fn clamp(&self, angle: Angle) -> Angle {
+        angle.clamp(-self.config.range / 2., self.config.range / 2.)
+    }
+    fn write(&mut self) -> Result<(), PWM::Error> {
+        let duty = self.config.duty(
+            self.angle / (self.config.range / 2.),
+            self.pwm.max_duty_cycle(),
+        );
+        self.pwm.set_duty_cycle(duty)
+    }
+}
+
+// Continuous-rotation servos turn the pulse width into a speed, with the center
+// pulse meaning stop. `range` and `max_speed` are not used.
+pub struct ContinuousServo<PWM: SetDutyCycle> {
+    pwm: PWM,
+    config: ServoConfig,
+}
+
+impl<PWM: SetDutyCycle> ContinuousServo<PWM> {
+    pub fn new(pwm: PWM, config: ServoConfig) -> Self {
+        Self { pwm, config }
+    }
+    pub fn set_config(&mut self, config: ServoConfig) {
+        self.config = config;
+    }
+    pub fn into_inner(self) -> PWM {
+        self.pwm
+    }
+}
+
+impl<PWM: SetDutyCycle> Motor for ContinuousServo<PWM> {
+    type Error = PWM::Error;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        let speed = finite_speed(speed).value();
+        let duty = self.config.duty(speed, self.pwm.max_duty_cycle());
+        self.pwm.set_duty_cycle(duty)
+    }
+    // Holds the center pulse, which the servo actively stops at.
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.set_speed(Duty::ZERO)
+    }
+    // Without pulses the servo stops driving its motor.
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.pwm.set_duty_cycle_fully_off()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockPwm;
+
+    fn servo(max_speed: f32) -> Servo<MockPwm> {
+        let config = ServoConfig {
+            max_speed: AngularVelocity::from_radians_per_second(max_speed),
+            ..Default::default()
+        };
+        Servo::new(MockPwm::new(20000), config)
+    }
+
+    #[test]
+    fn maps_angle_to_pulse() {
+        let mut servo = servo(f32::INFINITY);
+        // One duty step per microsecond.
+        for (degrees, pulse) in [
+            (0., 1500),
+            (90., 2500),
+            (-90., 500),
+            (-45., 1000),
+            (120., 2500),
+        ] {
+            servo.set_angle(Angle::from_degrees(degrees)).unwrap();
+            assert_eq!(servo.pwm.duty, pulse, "{degrees}°");
+        }
+    }
+
+    #[test]
+    fn infinite_speed_snaps_to_target() {
+        let mut servo = servo(f32::INFINITY);
+        servo.move_to(Angle::from_degrees(45.));
+        servo.update(Duration::ZERO).unwrap();
+        assert_eq!(servo.angle(), Angle::from_degrees(45.));
+        assert!(servo.is_target_reached());
+        assert!(servo.pwm.duty.abs_diff(2000) <= 1);
+    }
+
+    #[test]
+    fn limits_speed() {
+        let mut servo = servo(1.);
+        servo.move_to(Angle::from_radians(0.25));
+        servo.update(Duration::ZERO).unwrap();
+        assert_eq!(servo.angle(), Angle::ZERO);
+        servo.update(Duration::from_millis(100)).unwrap();
+        assert!((servo.angle().radians() - 0.1).abs() < 1e-6);
+        servo.update(Duration::from_millis(100)).unwrap();
+        servo.update(Duration::from_millis(100)).unwrap();
+        assert!(servo.is_target_reached());
+
+        servo.move_to(Angle::ZERO);
+        servo.update(Duration::from_millis(100)).unwrap();
+        assert!((servo.angle().radians() - 0.15).abs() < 1e-6);
+    }
+
+    #[test]
+    fn zero_speed_holds_position() {
+        let mut servo = servo(0.);
+        servo.move_to(Angle::from_degrees(10.));
+        servo.update(Duration::from_millis(100)).unwrap();
+        assert_eq!(servo.angle(), Angle::ZERO);
+    }
+
+    fn continuous(config: ServoConfig) -> ContinuousServo<MockPwm> {
+        ContinuousServo::new(MockPwm::new(20000), config)
+    }
+
+    #[test]
+    fn maps_speed_to_pulse() {
+        let mut servo = continuous(Default::default());
+        for (speed, pulse) in [
+            (0., 1500),
+            (1., 2500),
+            (-1., 500),
+            (0.5, 2000),
+            (-0.25, 1250),
+            (2., 2500),
+        ] {
+            servo.set_speed(Duty::new(speed)).unwrap();
+            // Off by one where f32 rounding truncates the duty.
+            assert!(servo.pwm.duty.abs_diff(pulse) <= 1, "{speed}");
+        }
+    }
+
+    #[test]
+    fn applies_trim_and_inversion() {
+        let mut servo = continuous(ServoConfig {
+            center_trim: 20.,
+            inverted: true,
+            ..Default::default()
+        });
+        servo.set_speed(Duty::ZERO).unwrap();
+        assert!(servo.pwm.duty.abs_diff(1520) <= 1);
+        servo.set_speed(Duty::new(0.5)).unwrap();
+        assert!(servo.pwm.duty.abs_diff(1020) <= 1);
+        servo.set_speed(Duty::new(-0.5)).unwrap();
+        assert!(servo.pwm.duty.abs_diff(2020) <= 1);
+    }
+
+    #[test]
+    fn brakes_at_center_and_coasts_without_pulses() {
+        let mut servo = continuous(Default::default());
+        servo.set_speed(Duty::new(0.5)).unwrap();
+        servo.brake().unwrap();
+        assert_eq!(servo.pwm.duty, 1500);
+        servo.coast().unwrap();
+        assert_eq!(servo.pwm.duty, 0);
+    }
+
+    #[test]
+    fn nan_speed_stops() {
+        let mut servo = continuous(Default::default());
+        servo.set_speed(Duty::new(0.5)).unwrap();
+        servo.set_speed(Duty::new(f32::NAN)).unwrap();
+        assert_eq!(servo.pwm.duty, 1500);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_negative_speed() {
+        servo(-1.);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_negative_speed_in_set_config() {
+        let mut servo = servo(1.);
+        servo.set_config(ServoConfig {
+            max_speed: AngularVelocity::from_radians_per_second(-1.),
+            ..Default::default()
+        });
+    }
+}
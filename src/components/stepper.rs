@@ -0,0 +1,434 @@
+use core::convert::Infallible;
+
+use embedded_hal::digital::{InputPin, OutputPin};
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+use crate::units::{Angle, Length};
+
+use super::switch::Switch;
+
+// Positions, speeds and accelerations are in microsteps, i.e. full steps times
+// `microsteps`, per second (squared).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepperConfig {
+    pub steps_per_revolution: u32,
+    // Must match the driver's MS pins or DIP switches.
+    pub microsteps: u32,
+    // Distance travelled per revolution, e.g. the lead of a lead screw. Only used
+    // by the length methods.
+    pub travel_per_revolution: Length,
+    // Frequency `tick` is called at. A step pulse lasts one tick, so the step rate
+    // is at most half of it.
+    pub tick_freq: f32,
+    // Both must be positive; an infinite acceleration changes speed at once.
+    pub max_speed: f32,
+    pub acceleration: f32,
+    pub inverted: bool,
+}
+
+impl StepperConfig {
+    fn is_valid(&self) -> bool {
+        self.tick_freq > 0.
+            && self.tick_freq.is_finite()
+            && self.max_speed > 0.
+            && self.acceleration > 0.
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StepperError<STEP, DIR> {
+    Step(STEP),
+    Dir(DIR),
+}
+
+impl From<StepperError<Infallible, Infallible>> for Infallible {
+    fn from(value: StepperError<Infallible, Infallible>) -> Self {
+        match value {
+            StepperError::Step(e) | StepperError::Dir(e) => e,
+        }
+    }
+}
+
+// STEP/DIR drivers such as the A4988, DRV8825 or TMC2208.
+pub struct Stepper<STEP: OutputPin, DIR: OutputPin> {
+    step: STEP,
+    dir: DIR,
+    config: StepperConfig,
+    position: i64,
+    target: i64,
+    // Signed speed and the fraction of a step accumulated towards the next one.
+    speed: f32,
+    phase: f32,
+    pulse_high: bool,
+    // Direction the DIR pin is set to, if it was set yet.
+    forward: Option<bool>,
+}
+
+impl<STEP: OutputPin, DIR: OutputPin> Stepper<STEP, DIR> {
+    pub fn new(step: STEP, dir: DIR, config: StepperConfig) -> Self {
+        assert!(config.is_valid());
+        Self {
+            step,
+            dir,
+            config,
+            position: 0,
+            target: 0,
+            speed: 0.,
+            phase: 0.,
+            pulse_high: false,
+            forward: None,
+        }
+    }
+    pub fn set_config(&mut self, config: StepperConfig) {
+        assert!(config.is_valid());
+        self.config = config;
+    }
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+    // Redefines the current position without moving, e.g. after homing.
+    pub fn set_position(&mut self, position: i64) {
+        self.target += position - self.position;
+        self.position = position;
+    }
+    pub fn position_angle(&self) -> Angle {
+        Angle::from_rotations(self.position as f32 / self.steps_per_revolution())
+    }
+    pub fn position_length(&self) -> Length {
+        self.config.travel_per_revolution * (self.position as f32 / self.steps_per_revolution())
+    }
+    pub fn target(&self) -> i64 {
+        self.target
+    }
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+    pub fn is_idle(&self) -> bool {
+        self.position == self.target && self.speed == 0.
+    }
+    pub fn move_to(&mut self, position: i64) {
+        self.target = position;
+    }
+    pub fn move_by(&mut self, steps: i64) {
+        self.target += steps;
+    }
+    pub fn move_to_angle(&mut self, angle: Angle) {
+        self.target = (angle.rotations() * self.steps_per_revolution()).round() as i64;
+    }
+    pub fn move_to_length(&mut self, position: Length) {
+        let rotations = position / self.config.travel_per_revolution;
+        self.target = (rotations * self.steps_per_revolution()).round() as i64;
+    }
+    // Decelerates to a stop as quickly as the acceleration allows.
+    pub fn stop(&mut self) {
+        let stopping_steps = (self.speed * self.speed / (2. * self.config.acceleration)).ceil();
+        self.target = self.position + (stopping_steps as i64) * self.speed.signum() as i64;
+    }
+    pub fn into_inner(self) -> (STEP, DIR) {
+        (self.step, self.dir)
+    }
+    // Advances the move by one tick with a trapezoidal speed profile, emitting at
+    // most one step. Call it from a timer interrupt at `tick_freq`.
+    pub fn tick(&mut self) -> Result<(), StepperError<STEP::Error, DIR::Error>> {
+        let pulse_ended = self.end_pulse()?;
+        let dt = 1. / self.config.tick_freq;
+        let acceleration = self.config.acceleration;
+        let remaining = (self.target - self.position) as f32;
+        let stopping_distance = self.speed * self.speed / (2. * acceleration);
+
+        if remaining == 0. && self.speed.abs() <= (2. * acceleration).sqrt() {
+            // Slow enough to stop within a step.
+            self.speed = 0.;
+            self.phase = 0.;
+            return Ok(());
+        }
+        if self.speed != 0.
+            && (self.speed.signum() != remaining.signum() || stopping_distance >= remaining.abs())
+        {
+            let speed = self.speed - self.speed.signum() * acceleration * dt;
+            self.speed = if speed.signum() == self.speed.signum() {
+                speed
+            } else {
+                0.
+            };
+        } else if remaining != 0. {
+            self.speed = (self.speed + remaining.signum() * acceleration * dt)
+                .clamp(-self.config.max_speed, self.config.max_speed);
+        }
+        self.advance(dt, pulse_ended)
+    }
+    // Moves towards a homing switch at a constant `speed`, which should be slow
+    // enough to stop within a step. Call it instead of `tick` until it returns
+    // true, at which point the switch position becomes zero.
+    pub fn tick_homing<P: InputPin>(
+        &mut self,
+        switch: &mut Switch<P>,
+        speed: f32,
+    ) -> Result<bool, StepperError<STEP::Error, DIR::Error>> {
+        let pulse_ended = self.end_pulse()?;
+        if switch.is_close() {
+            self.speed = 0.;
+            self.phase = 0.;
+            self.position = 0;
+            self.target = 0;
+            return Ok(true);
+        }
+        self.speed = speed;
+        self.target = self.position + speed.signum() as i64;
+        self.advance(1. / self.config.tick_freq, pulse_ended)?;
+        Ok(false)
+    }
+    fn steps_per_revolution(&self) -> f32 {
+        (self.config.steps_per_revolution * self.config.microsteps) as f32
+    }
+    // Returns whether a pulse was ended.
+    fn end_pulse(&mut self) -> Result<bool, StepperError<STEP::Error, DIR::Error>> {
+        if self.pulse_high {
+            self.step.set_low().map_err(StepperError::Step)?;
+            self.pulse_high = false;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+    // The step pin needs a low tick between pulses, and DIR has to settle before
+    // the next step (650 ns on a DRV8825), so a step due on the tick that ended the
+    // previous pulse or changed direction is postponed to the next one.
+    fn advance(
+        &mut self,
+        dt: f32,
+        pulse_ended: bool,
+    ) -> Result<(), StepperError<STEP::Error, DIR::Error>> {
+        self.phase += self.speed.abs() * dt;
+        if self.phase < 1. || self.position == self.target {
+            return Ok(());
+        }
+        let forward = self.speed > 0.;
+        let dir_changed = self.forward != Some(forward);
+        if dir_changed {
+            if forward != self.config.inverted {
+                self.dir.set_high().map_err(StepperError::Dir)?;
+            } else {
+                self.dir.set_low().map_err(StepperError::Dir)?;
+            }
+            self.forward = Some(forward);
+        }
+        if pulse_ended || dir_changed {
+            self.phase = self.phase.min(1.);
+            return Ok(());
+        }
+        self.phase -= 1.;
+
+        self.step.set_high().map_err(StepperError::Step)?;
+        self.pulse_high = true;
+        self.position += if forward { 1 } else { -1 };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockPin;
+
+    const CONFIG: StepperConfig = StepperConfig {
+        steps_per_revolution: 200,
+        microsteps: 1,
+        travel_per_revolution: Length::ZERO,
+        tick_freq: 1000.,
+        max_speed: 500.,
+        acceleration: 1e6,
+        inverted: false,
+    };
+
+    fn driver(config: StepperConfig) -> Stepper<MockPin, MockPin> {
+        Stepper::new(MockPin::default(), MockPin::default(), config)
+    }
+
+    // Ticks until idle and returns the number of ticks taken.
+    fn run(stepper: &mut Stepper<MockPin, MockPin>, max_ticks: usize) -> usize {
+        for ticks in 1..=max_ticks {
+            let was_high = stepper.step.high;
+            let dir = stepper.dir.high;
+            let position = stepper.position();
+            stepper.tick().unwrap();
+            if stepper.position() != position {
+                assert!(!was_high, "step without a low tick in between");
+                assert_eq!(stepper.dir.high, dir, "direction changed with a step");
+                assert!(stepper.step.high);
+            }
+            if stepper.is_idle() && !stepper.step.high {
+                return ticks;
+            }
+        }
+        panic!("move didn't finish");
+    }
+
+    #[test]
+    fn steps_at_half_tick_rate() {
+        let mut stepper = driver(CONFIG);
+        stepper.move_to(100);
+        let ticks = run(&mut stepper, 1000);
+        assert_eq!(stepper.position(), 100);
+        // 100 steps at 500 steps/s take 200 ticks, plus a few to settle.
+        assert!(ticks <= 205, "took {ticks} ticks");
+    }
+
+    #[test]
+    fn accelerates_and_reaches_target() {
+        let config = StepperConfig {
+            acceleration: 2000.,
+            ..CONFIG
+        };
+        for target in [1, 37, 400, -250] {
+            let mut stepper = driver(config);
+            stepper.move_to(target);
+            run(&mut stepper, 10000);
+            assert_eq!(stepper.position(), target);
+            assert_eq!(stepper.speed(), 0.);
+        }
+    }
+
+    #[test]
+    fn sets_direction() {
+        let mut stepper = driver(CONFIG);
+        stepper.move_by(-3);
+        stepper.tick().unwrap();
+        stepper.tick().unwrap();
+        assert!(!stepper.dir.high);
+        run(&mut stepper, 100);
+        stepper.move_by(3);
+        stepper.tick().unwrap();
+        stepper.tick().unwrap();
+        assert!(stepper.dir.high);
+
+        let mut inverted = driver(StepperConfig {
+            inverted: true,
+            ..CONFIG
+        });
+        inverted.move_by(3);
+        inverted.tick().unwrap();
+        inverted.tick().unwrap();
+        assert!(!inverted.dir.high);
+    }
+
+    #[test]
+    fn stop_decelerates() {
+        let config = StepperConfig {
+            acceleration: 1000.,
+            ..CONFIG
+        };
+        let mut stepper = driver(config);
+        stepper.move_to(10000);
+        for _ in 0..500 {
+            stepper.tick().unwrap();
+        }
+        assert_eq!(stepper.speed(), 500.);
+        stepper.stop();
+        // 500² / (2 * 1000) = 125 steps.
+        assert_eq!(stepper.target() - stepper.position(), 125);
+        run(&mut stepper, 10000);
+    }
+
+    #[test]
+    fn changes_direction_a_tick_before_stepping() {
+        let mut stepper = driver(CONFIG);
+        stepper.move_by(-3);
+        run(&mut stepper, 100);
+        stepper.move_by(3);
+        run(&mut stepper, 100);
+        assert_eq!(stepper.position(), 0);
+        assert!(stepper.dir.high);
+    }
+
+    #[test]
+    fn converts_lengths() {
+        // An 8 mm lead screw with half steps moves 50 steps per mm.
+        let mut stepper = driver(StepperConfig {
+            microsteps: 2,
+            travel_per_revolution: Length::from_millimeters(8.),
+            ..CONFIG
+        });
+        stepper.move_to_length(Length::from_millimeters(1.5));
+        assert_eq!(stepper.target(), 75);
+        run(&mut stepper, 1000);
+        assert!((stepper.position_length().millimeters() - 1.5).abs() < 1e-5);
+
+        // Targets round to the nearest step.
+        stepper.move_to_length(Length::from_millimeters(-0.011));
+        assert_eq!(stepper.target(), -1);
+        stepper.set_position(25);
+        assert!((stepper.position_length().millimeters() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn converts_angles() {
+        let mut stepper = driver(StepperConfig {
+            microsteps: 4,
+            ..CONFIG
+        });
+        // 800 microsteps per revolution.
+        stepper.move_to_angle(Angle::from_degrees(90.));
+        assert_eq!(stepper.target(), 200);
+        stepper.move_to_angle(Angle::from_degrees(-0.3));
+        assert_eq!(stepper.target(), -1);
+        stepper.set_position(-400);
+        assert!((stepper.position_angle().degrees() + 180.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn homes_on_switch() {
+        let mut stepper = driver(CONFIG);
+        stepper.set_position(100);
+        // The switch closes 5 steps below where the stepper started.
+        let mut steps = 0;
+        let mut homed = false;
+        for _ in 0..200 {
+            let position = stepper.position();
+            let mut switch = Switch::new(MockPin { high: steps <= -5 }, false);
+            if stepper.tick_homing(&mut switch, -100.).unwrap() {
+                homed = true;
+                break;
+            }
+            steps += stepper.position() - position;
+            assert!(!stepper.dir.high);
+        }
+        assert!(homed);
+        assert_eq!(steps, -5);
+        assert_eq!(stepper.position(), 0);
+        assert!(stepper.is_idle());
+        assert!(!stepper.step.high);
+
+        stepper.move_to(3);
+        run(&mut stepper, 100);
+        assert_eq!(stepper.position(), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_acceleration() {
+        driver(StepperConfig {
+            acceleration: 0.,
+            ..CONFIG
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_tick_freq() {
+        driver(StepperConfig {
+            tick_freq: 0.,
+            ..CONFIG
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_negative_max_speed_in_set_config() {
+        let mut stepper = driver(CONFIG);
+        stepper.set_config(StepperConfig {
+            max_speed: -1.,
+            ..CONFIG
+        });
+    }
+}
@@ -0,0 +1,306 @@
+use core::time::Duration;
+
+use advanced_pid::{prelude::*, PidConfig, PidGain, VelPid};
+
+use crate::units::{AngularVelocity, Duty};
+
+use super::{
+    motor::Motor,
+    rotary_encoder::Incremental,
+    velocity_estimator::{angular_velocity_from_ticks, VelocityEstimator},
+};
+
+// Closes the loop around a motor and an incremental encoder. `update` must be
+// called once every `period()`, e.g. from a timer interrupt or ticker task.
+pub struct VelocityControlledMotor<M: Motor, E: Incremental> {
+    motor: M,
+    encoder: E,
+    estimator: VelocityEstimator,
+    pid: VelPid,
+    // `VelPid` can only be retuned by resetting it, so the output it integrated up
+    // to then is carried over here.
+    pid_offset: f32,
+    feedback: f32,
+    // RPM error of the last update, which a reset `VelPid` no longer remembers.
+    error: f32,
+    gain: PidGain,
+    control_freq: f32,
+    feed_forward: f32,
+    target: AngularVelocity,
+    // Duty applied instead of the PID output while running open loop.
+    open_loop: Option<Duty>,
+    measured: AngularVelocity,
+    output: Duty,
+}
+
+impl<M: Motor, E: Incremental> VelocityControlledMotor<M, E> {
+    // Gains map an RPM error to normalized duty. `feed_forward` is the duty per RPM
+    // that is applied on top of the PID output. `control_freq` must be positive.
+    pub fn new(motor: M, encoder: E, gain: PidGain, feed_forward: f32, control_freq: f32) -> Self {
+        assert!(control_freq > 0. && control_freq.is_finite());
+        let mut controller = Self {
+            motor,
+            encoder,
+            estimator: VelocityEstimator::default(),
+            pid: VelPid::default(),
+            pid_offset: 0.,
+            feedback: 0.,
+            error: 0.,
+            gain,
+            control_freq,
+            feed_forward,
+            target: AngularVelocity::ZERO,
+            open_loop: None,
+            measured: AngularVelocity::ZERO,
+            output: Duty::ZERO,
+        };
+        controller.reset_pid();
+        controller
+    }
+    pub fn set_target_velocity(&mut self, velocity: AngularVelocity) {
+        if self.open_loop.take().is_some() {
+            self.reset_pid();
+        }
+        self.target = velocity;
+    }
+    // Drives the motor with a fixed duty until the next `set_target_velocity`. The
+    // speed is still measured on every `update`.
+    pub fn set_duty(&mut self, duty: Duty) {
+        self.open_loop = Some(duty);
+    }
+    pub fn is_open_loop(&self) -> bool {
+        self.open_loop.is_some()
+    }
+    pub fn target_velocity(&self) -> AngularVelocity {
+        self.target
+    }
+    // Defaults to the difference of successive positions.
+    pub fn set_estimator(&mut self, estimator: impl Into<VelocityEstimator>) {
+        self.estimator = estimator.into();
+    }
+    pub fn measured_velocity(&self) -> AngularVelocity {
+        self.measured
+    }
+    // The last duty sent to the motor.
+    pub fn output(&self) -> Duty {
+        self.output
+    }
+    pub fn gain(&self) -> &PidGain {
+        &self.gain
+    }
+    pub fn set_p_gain(&mut self, kp: f32) {
+        self.gain.kp = kp;
+        self.retune_pid();
+    }
+    pub fn set_i_gain(&mut self, ki: f32) {
+        self.gain.ki = ki;
+        self.retune_pid();
+    }
+    pub fn set_d_gain(&mut self, kd: f32) {
+        self.gain.kd = kd;
+        self.retune_pid();
+    }
+    pub fn set_feed_forward(&mut self, feed_forward: f32) {
+        self.feed_forward = feed_forward;
+    }
+    pub fn set_control_freq(&mut self, control_freq: f32) {
+        assert!(control_freq > 0. && control_freq.is_finite());
+        self.control_freq = control_freq;
+        self.retune_pid();
+    }
+    pub fn control_freq(&self) -> f32 {
+        self.control_freq
+    }
+    pub fn period(&self) -> Duration {
+        Duration::from_secs_f32(1. / self.control_freq)
+    }
+    pub fn update(&mut self) -> Result<(), M::Error> {
+        let dt = 1. / self.control_freq;
+        let position = self.encoder.position();
+        let velocity = self.estimator.update(position, self.period());
+        self.measured = angular_velocity_from_ticks(velocity, self.encoder.resolution());
+
+        self.output = match self.open_loop {
+            Some(duty) => duty,
+            None => {
+                let (target, measured) = (self.target.rpm(), self.measured.rpm());
+                self.feedback = self.pid_offset + self.pid.update(target, measured, dt);
+                self.error = target - measured;
+                Duty::new(self.feed_forward * target + self.feedback)
+            }
+        };
+        self.motor.set_speed(self.output)
+    }
+    pub fn stop(&mut self) -> Result<(), M::Error> {
+        self.target = AngularVelocity::ZERO;
+        self.open_loop = None;
+        self.output = Duty::ZERO;
+        self.reset_pid();
+        self.motor.brake()
+    }
+    pub fn motor_mut(&mut self) -> &mut M {
+        &mut self.motor
+    }
+    pub fn into_inner(self) -> (M, E) {
+        (self.motor, self.encoder)
+    }
+    fn reset_pid(&mut self) {
+        self.feedback = 0.;
+        self.error = 0.;
+        self.retune_pid();
+    }
+    // Applies new gains without dropping the integrated output, so the duty doesn't
+    // jump. The reset `VelPid` adds the proportional term of the whole error again
+    // on its next update, so that is taken out of the offset. The limits are
+    // shifted to keep the total within the duty range.
+    fn retune_pid(&mut self) {
+        self.pid_offset = self.feedback - self.gain.kp * self.error;
+        let config = velocity_pid_config(&self.gain, self.control_freq)
+            .with_limits(-1. - self.pid_offset, 1. - self.pid_offset);
+        self.pid.reset_config(config);
+    }
+}
+
+// `VelPid` integrates its output, and clamping that to the duty range keeps the
+// integral from winding up. It also leaves out `dt` from its increments, so the
+// gains are scaled here to keep them independent of the control frequency.
+pub(crate) fn velocity_pid_config(gain: &PidGain, control_freq: f32) -> PidConfig {
+    let dt = 1. / control_freq;
+    PidConfig::new(gain.kp * dt, gain.ki * dt, gain.kd * dt).with_limits(-1., 1.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEncoder, MockMotor};
+
+    fn motor() -> VelocityControlledMotor<MockMotor, MockEncoder> {
+        let gain = PidGain {
+            kp: 0.,
+            ki: 0.001,
+            kd: 0.,
+        };
+        let mut motor = VelocityControlledMotor::new(
+            MockMotor::default(),
+            MockEncoder::new(1000),
+            gain,
+            0.,
+            100.,
+        );
+        motor.set_target_velocity(AngularVelocity::from_rpm(600.));
+        motor
+    }
+
+    #[test]
+    fn integrates_towards_target() {
+        let mut motor = motor();
+        let mut last = Duty::ZERO;
+        for _ in 0..10 {
+            motor.update().unwrap();
+            assert!(motor.output() > last);
+            last = motor.output();
+        }
+    }
+
+    #[test]
+    fn retuning_keeps_output() {
+        for retune in [
+            |motor: &mut VelocityControlledMotor<_, _>| motor.set_p_gain(0.0001),
+            |motor: &mut VelocityControlledMotor<_, _>| motor.set_i_gain(0.001),
+            |motor: &mut VelocityControlledMotor<_, _>| motor.set_d_gain(0.),
+            |motor: &mut VelocityControlledMotor<_, _>| motor.set_control_freq(100.),
+        ] {
+            let mut motor = motor();
+            motor.set_p_gain(0.0001);
+            for _ in 0..10 {
+                motor.update().unwrap();
+            }
+            // The error stays constant as the encoder doesn't move, so only the
+            // integral term changes the output from one update to the next.
+            let output = motor.output();
+            motor.update().unwrap();
+            let step = motor.output() - output;
+            assert!(step > Duty::ZERO);
+            // Re-applying the same gains changes nothing.
+            let output = motor.output();
+            retune(&mut motor);
+            motor.update().unwrap();
+            assert!(
+                ((motor.output() - output) - step).abs() < Duty::new(1e-6),
+                "{:?} -> {:?}",
+                output,
+                motor.output()
+            );
+        }
+    }
+
+    #[test]
+    fn retuning_continues_from_output() {
+        let mut motor = motor();
+        motor.set_p_gain(0.0001);
+        for _ in 0..10 {
+            motor.update().unwrap();
+        }
+        let output = motor.output();
+        // Without an integral term the output holds at a constant error.
+        motor.set_i_gain(0.);
+        for _ in 0..3 {
+            motor.update().unwrap();
+            assert!((motor.output() - output).abs() < Duty::new(1e-6));
+        }
+        // A larger proportional gain only acts on changes of the error.
+        motor.set_p_gain(0.001);
+        motor.update().unwrap();
+        assert!((motor.output() - output).abs() < Duty::new(1e-6));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_control_freq() {
+        motor().set_control_freq(0.);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_nan_control_freq() {
+        let gain = PidGain {
+            kp: 0.,
+            ki: 0.,
+            kd: 0.,
+        };
+        VelocityControlledMotor::new(
+            MockMotor::default(),
+            MockEncoder::new(1000),
+            gain,
+            0.,
+            f32::NAN,
+        );
+    }
+
+    #[test]
+    fn retuned_output_stays_in_duty_range() {
+        let mut motor = motor();
+        motor.set_i_gain(1.);
+        motor.update().unwrap();
+        assert_eq!(motor.output(), Duty::MAX);
+        motor.set_i_gain(2.);
+        motor.update().unwrap();
+        assert_eq!(motor.output(), Duty::MAX);
+        motor.set_target_velocity(AngularVelocity::from_rpm(-600.));
+        motor.set_i_gain(1.);
+        motor.update().unwrap();
+        assert_eq!(motor.output(), Duty::MIN);
+    }
+
+    #[test]
+    fn stop_resets_output() {
+        let mut motor = motor();
+        for _ in 0..10 {
+            motor.update().unwrap();
+        }
+        motor.stop().unwrap();
+        motor.set_target_velocity(AngularVelocity::ZERO);
+        motor.update().unwrap();
+        assert_eq!(motor.output(), Duty::ZERO);
+    }
+}
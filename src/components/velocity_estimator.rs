@@ -0,0 +1,363 @@
+use core::{f32::consts::PI, time::Duration};
+
+use heapless::Deque;
+
+use crate::units::AngularVelocity;
+
+// Velocities are in encoder ticks per second. Every estimator is fed the
+// position once per control period, and returns zero until it has seen two.
+pub fn angular_velocity_from_ticks(ticks_per_second: f32, resolution: u32) -> AngularVelocity {
+    AngularVelocity::from_rotations_per_second(ticks_per_second / resolution as f32)
+}
+
+// Difference of two successive positions. Noisy at low speed, where only a few
+// ticks arrive per period.
+#[derive(Debug, Clone, Default)]
+pub struct DifferenceEstimator {
+    last: Option<i64>,
+    velocity: f32,
+}
+
+impl DifferenceEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn velocity(&self) -> f32 {
+        self.velocity
+    }
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+    pub fn update(&mut self, position: i64, dt: Duration) -> f32 {
+        if let Some(last) = self.last.replace(position) {
+            self.velocity = (position - last) as f32 / dt.as_secs_f32();
+        }
+        self.velocity
+    }
+}
+
+pub const MOVING_AVERAGE_MAX_WINDOW: usize = 16;
+
+// Difference over the last `window` periods, trading latency for resolution.
+#[derive(Debug, Clone)]
+pub struct MovingAverageEstimator {
+    window: usize,
+    last: Option<i64>,
+    // Position change and length of each period in the window.
+    samples: Deque<(i32, f32), MOVING_AVERAGE_MAX_WINDOW>,
+    velocity: f32,
+}
+
+impl MovingAverageEstimator {
+    // `window` is clamped to 1..=MOVING_AVERAGE_MAX_WINDOW.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.clamp(1, MOVING_AVERAGE_MAX_WINDOW),
+            last: None,
+            samples: Deque::new(),
+            velocity: 0.,
+        }
+    }
+    pub fn velocity(&self) -> f32 {
+        self.velocity
+    }
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.samples.clear();
+        self.velocity = 0.;
+    }
+    pub fn update(&mut self, position: i64, dt: Duration) -> f32 {
+        let Some(last) = self.last.replace(position) else {
+            return self.velocity;
+        };
+        if self.samples.len() >= self.window {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back(((position - last) as i32, dt.as_secs_f32()))
+            .unwrap();
+        let (distance, elapsed) = self
+            .samples
+            .iter()
+            .fold((0, 0.), |(distance, elapsed), (delta, dt)| {
+                (distance + *delta as i64, elapsed + dt)
+            });
+        if elapsed > 0. {
+            self.velocity = distance as f32 / elapsed;
+        }
+        self.velocity
+    }
+}
+
+// Measures the time between position changes (1/T) instead of counting ticks per
+// period, which resolves speeds below one tick per period. While no tick
+// arrives the estimate decays as if one were just about to, and drops to zero
+// after `timeout`.
+#[derive(Debug, Clone)]
+pub struct PeriodEstimator {
+    timeout: Duration,
+    last: Option<i64>,
+    elapsed: Duration,
+    velocity: f32,
+}
+
+impl PeriodEstimator {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last: None,
+            elapsed: Duration::ZERO,
+            velocity: 0.,
+        }
+    }
+    pub fn velocity(&self) -> f32 {
+        self.velocity
+    }
+    pub fn reset(&mut self) {
+        *self = Self::new(self.timeout);
+    }
+    pub fn update(&mut self, position: i64, dt: Duration) -> f32 {
+        let Some(last) = self.last else {
+            self.last = Some(position);
+            return self.velocity;
+        };
+        self.elapsed += dt;
+        let elapsed = self.elapsed.as_secs_f32();
+        if position != last {
+            self.velocity = (position - last) as f32 / elapsed;
+            self.last = Some(position);
+            self.elapsed = Duration::ZERO;
+        } else if self.elapsed >= self.timeout {
+            self.velocity = 0.;
+        } else if self.velocity.abs() * elapsed > 1. {
+            self.velocity = self.velocity.signum() / elapsed;
+        }
+        self.velocity
+    }
+}
+
+// A second order tracking loop (PLL) following the position, which gives a
+// smooth, low-latency estimate. `bandwidth` is in Hz and the loop is critically
+// damped.
+#[derive(Debug, Clone)]
+pub struct PllEstimator {
+    kp: f32,
+    ki: f32,
+    last: Option<i64>,
+    // Estimated minus measured position, kept small to stay precise in `f32`.
+    offset: f32,
+    velocity: f32,
+}
+
+impl PllEstimator {
+    pub fn new(bandwidth: f32) -> Self {
+        let omega = 2. * PI * bandwidth;
+        Self {
+            kp: 2. * omega,
+            ki: omega * omega,
+            last: None,
+            offset: 0.,
+            velocity: 0.,
+        }
+    }
+    pub fn velocity(&self) -> f32 {
+        self.velocity
+    }
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.offset = 0.;
+        self.velocity = 0.;
+    }
+    pub fn update(&mut self, position: i64, dt: Duration) -> f32 {
+        let Some(last) = self.last.replace(position) else {
+            return self.velocity;
+        };
+        let dt = dt.as_secs_f32();
+        let delta = (position - last) as f32;
+        let error = delta - self.offset;
+        self.velocity += self.ki * error * dt;
+        self.offset += (self.velocity + self.kp * error) * dt - delta;
+        self.velocity
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum VelocityEstimator {
+    Difference(DifferenceEstimator),
+    MovingAverage(MovingAverageEstimator),
+    Period(PeriodEstimator),
+    Pll(PllEstimator),
+}
+
+impl Default for VelocityEstimator {
+    fn default() -> Self {
+        Self::Difference(DifferenceEstimator::new())
+    }
+}
+
+impl VelocityEstimator {
+    pub fn velocity(&self) -> f32 {
+        match self {
+            Self::Difference(e) => e.velocity(),
+            Self::MovingAverage(e) => e.velocity(),
+            Self::Period(e) => e.velocity(),
+            Self::Pll(e) => e.velocity(),
+        }
+    }
+    pub fn reset(&mut self) {
+        match self {
+            Self::Difference(e) => e.reset(),
+            Self::MovingAverage(e) => e.reset(),
+            Self::Period(e) => e.reset(),
+            Self::Pll(e) => e.reset(),
+        }
+    }
+    pub fn update(&mut self, position: i64, dt: Duration) -> f32 {
+        match self {
+            Self::Difference(e) => e.update(position, dt),
+            Self::MovingAverage(e) => e.update(position, dt),
+            Self::Period(e) => e.update(position, dt),
+            Self::Pll(e) => e.update(position, dt),
+        }
+    }
+}
+
+impl From<DifferenceEstimator> for VelocityEstimator {
+    fn from(value: DifferenceEstimator) -> Self {
+        Self::Difference(value)
+    }
+}
+
+impl From<MovingAverageEstimator> for VelocityEstimator {
+    fn from(value: MovingAverageEstimator) -> Self {
+        Self::MovingAverage(value)
+    }
+}
+
+impl From<PeriodEstimator> for VelocityEstimator {
+    fn from(value: PeriodEstimator) -> Self {
+        Self::Period(value)
+    }
+}
+
+impl From<PllEstimator> for VelocityEstimator {
+    fn from(value: PllEstimator) -> Self {
+        Self::Pll(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::rotary_encoder::CounterUnwrapper;
+
+    const DT: Duration = Duration::from_millis(1);
+
+    // Feeds a 16-bit counter turning at `ticks_per_period`, starting 100 ticks
+    // before it wraps around, through the unwrapper into `estimate`.
+    fn ramp(
+        ticks_per_period: f32,
+        periods: usize,
+        mut estimate: impl FnMut(i64, Duration) -> f32,
+    ) -> f32 {
+        let start = if ticks_per_period >= 0. {
+            u16::MAX - 100
+        } else {
+            100
+        };
+        let mut unwrapper = CounterUnwrapper::for_u16(start);
+        let mut velocity = 0.;
+        for i in 0..periods {
+            let count = start.wrapping_add((i as f32 * ticks_per_period) as i32 as u16);
+            velocity = estimate(unwrapper.update(count.into()), DT);
+        }
+        velocity
+    }
+
+    fn assert_close(velocity: f32, expected: f32) {
+        assert!(
+            (velocity - expected).abs() <= expected.abs() * 0.01,
+            "{velocity} != {expected}"
+        );
+    }
+
+    #[test]
+    fn returns_zero_until_second_position() {
+        let mut estimators: [VelocityEstimator; 4] = [
+            DifferenceEstimator::new().into(),
+            MovingAverageEstimator::new(4).into(),
+            PeriodEstimator::new(Duration::from_millis(100)).into(),
+            PllEstimator::new(50.).into(),
+        ];
+        for estimator in &mut estimators {
+            assert_eq!(estimator.update(1000, DT), 0.);
+            assert!(estimator.update(1010, DT) > 0.);
+            estimator.reset();
+            assert_eq!(estimator.velocity(), 0.);
+            assert_eq!(estimator.update(0, DT), 0.);
+        }
+    }
+
+    #[test]
+    fn difference_follows_ramp_across_wrap() {
+        let mut estimator = DifferenceEstimator::new();
+        let velocity = ramp(5., 100, |position, dt| estimator.update(position, dt));
+        assert_close(velocity, 5000.);
+        let mut estimator = DifferenceEstimator::new();
+        let velocity = ramp(-7., 100, |position, dt| estimator.update(position, dt));
+        assert_close(velocity, -7000.);
+    }
+
+    #[test]
+    fn moving_average_follows_ramp_across_wrap() {
+        // 2.5 ticks per period alternate between 2 and 3, which the window evens out.
+        let mut estimator = MovingAverageEstimator::new(4);
+        let velocity = ramp(2.5, 100, |position, dt| estimator.update(position, dt));
+        assert_close(velocity, 2500.);
+        let mut estimator = MovingAverageEstimator::new(100);
+        let velocity = ramp(-3., 100, |position, dt| estimator.update(position, dt));
+        assert_close(velocity, -3000.);
+    }
+
+    #[test]
+    fn period_resolves_slow_ramp_across_wrap() {
+        // One tick every fourth period.
+        let mut estimator = PeriodEstimator::new(Duration::from_millis(100));
+        let velocity = ramp(0.25, 800, |position, dt| estimator.update(position, dt));
+        assert_close(velocity, 250.);
+        let mut estimator = PeriodEstimator::new(Duration::from_millis(100));
+        let velocity = ramp(-20., 100, |position, dt| estimator.update(position, dt));
+        assert_close(velocity, -20000.);
+    }
+
+    #[test]
+    fn period_decays_and_times_out() {
+        let mut estimator = PeriodEstimator::new(Duration::from_millis(10));
+        estimator.update(0, DT);
+        assert_close(estimator.update(2, DT), 2000.);
+        // Nothing moves, so a tick can't be faster than one per elapsed time.
+        assert_close(estimator.update(2, DT), 1000.);
+        assert_close(estimator.update(2, DT), 500.);
+        assert_close(estimator.update(2, DT), 1000. / 3.);
+        for _ in 0..6 {
+            assert!(estimator.update(2, DT) > 0.);
+        }
+        assert_eq!(estimator.update(2, DT), 0.);
+    }
+
+    #[test]
+    fn pll_follows_ramp_across_wrap() {
+        let mut estimator = PllEstimator::new(50.);
+        let velocity = ramp(2.5, 500, |position, dt| estimator.update(position, dt));
+        assert_close(velocity, 2500.);
+        let mut estimator = PllEstimator::new(50.);
+        let velocity = ramp(-40., 500, |position, dt| estimator.update(position, dt));
+        assert_close(velocity, -40000.);
+    }
+
+    #[test]
+    fn converts_ticks_to_angular_velocity() {
+        let velocity = angular_velocity_from_ticks(4096., 1024);
+        assert!((velocity.rotations_per_second() - 4.).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod serial;
+#[cfg(feature = "tokio")]
+pub mod tokio_io;
@@ -0,0 +1,134 @@
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::{fd::AsFd, unix::fs::OpenOptionsExt},
+    path::Path,
+};
+
+use nix::{
+    libc,
+    pty::openpty,
+    sys::termios::{self, BaudRate, SetArg},
+};
+
+// A Linux tty (USB serial adapter, UART or pseudo-terminal) in raw mode, usable as
+// a blocking SBTP transport.
+pub struct SerialPort {
+    file: File,
+}
+
+impl SerialPort {
+    pub fn open(path: impl AsRef<Path>, baud_rate: u32) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)?;
+        let port = Self { file };
+        port.configure(Some(baud_rate))?;
+        Ok(port)
+    }
+    // Returns both ends of a fresh pseudo-terminal, which behave like two serial
+    // ports connected by a cable.
+    pub fn pty_pair() -> io::Result<(Self, Self)> {
+        let pty = openpty(None, None)?;
+        let master = Self {
+            file: File::from(pty.master),
+        };
+        let slave = Self {
+            file: File::from(pty.slave),
+        };
+        master.configure(None)?;
+        slave.configure(None)?;
+        Ok((master, slave))
+    }
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            file: self.file.try_clone()?,
+        })
+    }
+    fn configure(&self, baud_rate: Option<u32>) -> io::Result<()> {
+        let mut attrs = termios::tcgetattr(self.file.as_fd())?;
+        termios::cfmakeraw(&mut attrs);
+        if let Some(baud_rate) = baud_rate {
+            termios::cfsetspeed(&mut attrs, baud_rate_from_u32(baud_rate)?)?;
+        }
+        termios::tcsetattr(self.file.as_fd(), SetArg::TCSANOW, &attrs)?;
+        Ok(())
+    }
+}
+
+fn baud_rate_from_u32(baud_rate: u32) -> io::Result<BaudRate> {
+    let baud_rate = match baud_rate {
+        9600 => BaudRate::B9600,
+        19200 => BaudRate::B19200,
+        38400 => BaudRate::B38400,
+        57600 => BaudRate::B57600,
+        115200 => BaudRate::B115200,
+        230400 => BaudRate::B230400,
+        460800 => BaudRate::B460800,
+        500000 => BaudRate::B500000,
+        921600 => BaudRate::B921600,
+        1000000 => BaudRate::B1000000,
+        1500000 => BaudRate::B1500000,
+        2000000 => BaudRate::B2000000,
+        3000000 => BaudRate::B3000000,
+        4000000 => BaudRate::B4000000,
+        _ => return Err(io::ErrorKind::InvalidInput.into()),
+    };
+    Ok(baud_rate)
+}
+
+impl embedded_io::ErrorType for SerialPort {
+    type Error = io::Error;
+}
+
+impl embedded_io::Read for SerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        io::Read::read(&mut self.file, buf)
+    }
+}
+
+impl embedded_io::Write for SerialPort {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        io::Write::write(&mut self.file, buf)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        io::Write::flush(&mut self.file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sbtp::{blocking::Sbtp, fragment::Reassembler, frame::Framing};
+
+    #[test]
+    fn sbtp_round_trips_over_pty() {
+        for framing in [Framing::Escape, Framing::Cobs] {
+            let (master, slave) = SerialPort::pty_pair().unwrap();
+            let mut master = Sbtp::with_framing(master, framing);
+            let mut slave = Sbtp::with_framing(slave, framing);
+
+            master.send(&[0x00, 0x55, 0x5A, 0xAA, 0x01]).unwrap();
+            assert_eq!(
+                &slave.receive().unwrap()[..],
+                &[0x00, 0x55, 0x5A, 0xAA, 0x01]
+            );
+
+            slave.send(&[]).unwrap();
+            assert_eq!(&master.receive().unwrap()[..], &[]);
+
+            let data: std::vec::Vec<u8> = (0..600).map(|i| i as u8).collect();
+            master.send_fragmented(&data).unwrap();
+            let mut reassembler = Reassembler::<1024>::new();
+            assert_eq!(
+                slave.receive_fragmented(&mut reassembler).unwrap(),
+                &data[..]
+            );
+
+            assert_eq!(master.stats().frames_sent, 4);
+            assert_eq!(slave.stats().frames_received, 4);
+        }
+    }
+}
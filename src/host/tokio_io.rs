@@ -0,0 +1,46 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+
+use crate::sbtp::split::Split;
+
+// Adapts a tokio stream (e.g. a tokio-serial port or a TCP bridge) into an async
+// SBTP transport.
+pub struct TokioIo<T>(T);
+
+impl<T> TokioIo<T> {
+    pub fn new(inner: T) -> Self {
+        Self(inner)
+    }
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> embedded_io_async::ErrorType for TokioIo<T> {
+    type Error = io::Error;
+}
+
+impl<T: AsyncRead + Unpin> embedded_io_async::Read for TokioIo<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await
+    }
+}
+
+impl<T: AsyncWrite + Unpin> embedded_io_async::Write for TokioIo<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.write(buf).await
+    }
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush().await
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite> Split for TokioIo<T> {
+    type Reader = TokioIo<ReadHalf<T>>;
+    type Writer = TokioIo<WriteHalf<T>>;
+    fn split(self) -> (Self::Reader, Self::Writer) {
+        let (reader, writer) = tokio::io::split(self.0);
+        (TokioIo(reader), TokioIo(writer))
+    }
+}
@@ -1,6 +1,14 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
 
 pub mod components;
+#[cfg(feature = "std")]
+pub mod host;
+#[cfg(test)]
+mod mock;
 pub mod node;
 pub mod sbtp;
+pub mod units;
 pub mod util;
@@ -0,0 +1,339 @@
+// Host-side stand-ins for the hardware traits, shared by the unit tests.
+
+use core::{
+    convert::Infallible,
+    future::Future,
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
+use std::collections::VecDeque;
+
+use embedded_hal::{
+    digital::{self, InputPin, OutputPin, StatefulOutputPin},
+    pwm::{self, SetDutyCycle},
+};
+
+use crate::{
+    components::{
+        current_sensor::CurrentSensor,
+        motor::Motor,
+        rotary_encoder::{Absolute, Incremental, RotaryEncoder},
+    },
+    node::{message::Message, transport::Transport},
+    units::Duty,
+};
+
+// Drives a future that never has to wait, which holds for everything backed by
+// these mocks.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+// A byte queue: what is written can be read back, and reading past the end
+// returns 0 bytes like a closed stream.
+#[derive(Default)]
+pub struct Pipe {
+    pub data: VecDeque<u8>,
+}
+
+impl Pipe {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn bytes(&self) -> std::vec::Vec<u8> {
+        self.data.iter().copied().collect()
+    }
+}
+
+impl embedded_io::ErrorType for Pipe {
+    type Error = Infallible;
+}
+
+impl embedded_io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.data.len());
+        for (dst, src) in buf.iter_mut().zip(self.data.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl embedded_io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.data.extend(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl embedded_io_async::Read for Pipe {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        embedded_io::Read::read(self, buf)
+    }
+}
+
+impl embedded_io_async::Write for Pipe {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io::Write::write(self, buf)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MotorState {
+    #[default]
+    Coast,
+    Brake,
+    Speed(Duty),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MockMotorError;
+
+// Remembers the last command. While `fail` is set, commands are counted but
+// rejected.
+#[derive(Default)]
+pub struct MockMotor {
+    pub state: MotorState,
+    pub calls: usize,
+    pub fail: bool,
+}
+
+impl MockMotor {
+    fn apply(&mut self, state: MotorState) -> Result<(), MockMotorError> {
+        self.calls += 1;
+        if self.fail {
+            return Err(MockMotorError);
+        }
+        self.state = state;
+        Ok(())
+    }
+}
+
+impl Motor for MockMotor {
+    type Error = MockMotorError;
+    fn set_speed(&mut self, speed: Duty) -> Result<(), Self::Error> {
+        self.apply(MotorState::Speed(speed))
+    }
+    fn brake(&mut self) -> Result<(), Self::Error> {
+        self.apply(MotorState::Brake)
+    }
+    fn coast(&mut self) -> Result<(), Self::Error> {
+        self.apply(MotorState::Coast)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MockPin {
+    pub high: bool,
+}
+
+impl digital::ErrorType for MockPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.high = false;
+        Ok(())
+    }
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.high = true;
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for MockPin {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.high)
+    }
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.high)
+    }
+}
+
+impl InputPin for MockPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.high)
+    }
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.high)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MockPwm {
+    pub duty: u16,
+    pub max_duty: u16,
+}
+
+impl MockPwm {
+    pub fn new(max_duty: u16) -> Self {
+        Self { duty: 0, max_duty }
+    }
+}
+
+impl pwm::ErrorType for MockPwm {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for MockPwm {
+    fn max_duty_cycle(&self) -> u16 {
+        self.max_duty
+    }
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        assert!(duty <= self.max_duty, "duty {duty} above {}", self.max_duty);
+        self.duty = duty;
+        Ok(())
+    }
+}
+
+// An incremental encoder whose position is set by the test.
+pub struct MockEncoder {
+    pub position: i64,
+    pub resolution: u32,
+}
+
+impl MockEncoder {
+    pub fn new(resolution: u32) -> Self {
+        Self {
+            position: 0,
+            resolution,
+        }
+    }
+}
+
+impl RotaryEncoder for MockEncoder {
+    fn resolution(&self) -> u32 {
+        self.resolution
+    }
+}
+
+impl Incremental for MockEncoder {
+    fn position(&mut self) -> i64 {
+        self.position
+    }
+    fn set_position(&mut self, position: i64) {
+        self.position = position;
+    }
+}
+
+// An absolute encoder whose position is set by the test.
+pub struct MockAbsoluteEncoder {
+    pub position: u32,
+    pub resolution: u32,
+}
+
+impl MockAbsoluteEncoder {
+    pub fn new(resolution: u32) -> Self {
+        Self {
+            position: 0,
+            resolution,
+        }
+    }
+}
+
+impl RotaryEncoder for MockAbsoluteEncoder {
+    fn resolution(&self) -> u32 {
+        self.resolution
+    }
+}
+
+impl Absolute for MockAbsoluteEncoder {
+    type Error = Infallible;
+    fn get_position(&mut self) -> Result<u32, Self::Error> {
+        Ok(self.position)
+    }
+}
+
+// Hands out the queued messages and keeps everything that is sent.
+#[derive(Default)]
+pub struct MockTransport<const N: usize> {
+    pub received: VecDeque<Message<N>>,
+    pub sent: std::vec::Vec<Message<N>>,
+}
+
+impl<const N: usize> Transport<N> for MockTransport<N> {
+    type Error = Infallible;
+    fn send(&mut self, message: Message<N>) -> Result<(), Self::Error> {
+        self.sent.push(message);
+        Ok(())
+    }
+    fn receive(&mut self) -> Result<Option<Message<N>>, Self::Error> {
+        Ok(self.received.pop_front())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockFrame {
+    id: embedded_can::Id,
+    data: std::vec::Vec<u8>,
+}
+
+impl embedded_can::Frame for MockFrame {
+    fn new(id: impl Into<embedded_can::Id>, data: &[u8]) -> Option<Self> {
+        (data.len() <= 8).then(|| Self {
+            id: id.into(),
+            data: data.to_vec(),
+        })
+    }
+    fn new_remote(_id: impl Into<embedded_can::Id>, _dlc: usize) -> Option<Self> {
+        None
+    }
+    fn is_extended(&self) -> bool {
+        matches!(self.id, embedded_can::Id::Extended(_))
+    }
+    fn is_remote_frame(&self) -> bool {
+        false
+    }
+    fn id(&self) -> embedded_can::Id {
+        self.id
+    }
+    fn dlc(&self) -> usize {
+        self.data.len()
+    }
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+// Keeps every transmitted frame and receives the queued ones.
+#[derive(Default)]
+pub struct MockCan {
+    pub sent: std::vec::Vec<MockFrame>,
+    pub received: VecDeque<MockFrame>,
+}
+
+impl embedded_can::blocking::Can for MockCan {
+    type Frame = MockFrame;
+    type Error = embedded_can::ErrorKind;
+    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
+        self.sent.push(frame.clone());
+        Ok(())
+    }
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        self.received
+            .pop_front()
+            .ok_or(embedded_can::ErrorKind::Other)
+    }
+}
+
+#[derive(Default)]
+pub struct MockCurrentSensor {
+    pub current: f32,
+}
+
+impl CurrentSensor for MockCurrentSensor {
+    type Error = Infallible;
+    fn current(&mut self) -> Result<f32, Self::Error> {
+        Ok(self.current)
+    }
+}
@@ -1,6 +1,6 @@
 use num_enum::{FromPrimitive, IntoPrimitive};
 
-#[derive(IntoPrimitive, FromPrimitive, Debug)]
+#[derive(IntoPrimitive, FromPrimitive, Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum Command {
     Stop = 0x00,
@@ -11,6 +11,8 @@ pub enum Command {
     NotifySwitchState = 0x5C,
     NotifyRpm = 0x5D,
     NotifyGamepadState = 0x5E,
+    NotifyLinkStats = 0x5F,
+    NotifyFault = 0x60,
     SetControlFreq = 0xAE,
     SetPGain = 0xAF,
     SetIGain = 0xB0,
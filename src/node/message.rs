@@ -69,6 +69,22 @@ impl<const N: usize> Message<N> {
         (self.from, self.to, self.command, self.payload)
     }
 
+    pub fn from(&self) -> Id {
+        self.from
+    }
+    pub fn to(&self) -> Id {
+        self.to
+    }
+    pub fn command(&self) -> Command {
+        self.command
+    }
+    pub fn header(&self) -> [u8; 3] {
+        [self.from.into(), self.to.into(), self.command.into()]
+    }
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
     pub fn into_vec<const N1: usize>(self) -> Vec<u8, N1> {
         let mut vec = Vec::new();
         vec.push(self.from.into()).unwrap();
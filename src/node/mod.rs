@@ -1,3 +1,6 @@
 pub mod command;
 pub mod id;
 pub mod message;
+pub mod motor_command;
+pub mod motor_node;
+pub mod transport;
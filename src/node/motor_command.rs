@@ -0,0 +1,264 @@
+use core::ops::RangeInclusive;
+
+use heapless::Vec;
+
+use crate::{
+    components::motor::protection::Fault,
+    units::{AngularVelocity, Duty},
+};
+
+use super::{command::Command, id::Id, message::Message};
+
+// Addresses every channel of a node in `Stop` and the gain commands.
+pub const ALL_CHANNELS: u8 = 0xFF;
+
+// Control frequencies a node accepts, in Hz.
+pub const CONTROL_FREQ_RANGE: RangeInclusive<f32> = 1.0..=100_000.0;
+
+// Payloads of the motor commands. Values are big-endian `f32`s following the
+// channel byte, except `SetControlFreq` which applies to the whole node. `Stop`
+// without a payload stops every channel. Velocities go over the wire in RPM.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[rustfmt::skip]
+pub enum MotorCommand {
+    Stop { channel: u8 },
+    SetDuty { channel: u8, duty: Duty },
+    SetRpm { channel: u8, velocity: AngularVelocity },
+    SetControlFreq { freq: f32 },
+    SetPGain { channel: u8, gain: f32 },
+    SetIGain { channel: u8, gain: f32 },
+    SetDGain { channel: u8, gain: f32 },
+    NotifyRpm { channel: u8, velocity: AngularVelocity },
+    // The filtered current in amperes when the fault tripped.
+    NotifyFault { channel: u8, fault: Fault, current: f32 },
+}
+
+impl MotorCommand {
+    // Payloads that are malformed or carry a NaN or infinite value are rejected.
+    pub fn parse(command: Command, payload: &[u8]) -> Option<Self> {
+        let value = |bytes: &[u8]| {
+            Some(f32::from_be_bytes(bytes.try_into().ok()?)).filter(|value| value.is_finite())
+        };
+        let channel_value = || match payload {
+            [channel, bytes @ ..] => Some((*channel, value(bytes)?)),
+            _ => None,
+        };
+        Some(match command {
+            Command::Stop => match payload {
+                [] => Self::Stop {
+                    channel: ALL_CHANNELS,
+                },
+                [channel] => Self::Stop { channel: *channel },
+                _ => return None,
+            },
+            Command::SetDuty => {
+                let (channel, duty) = channel_value()?;
+                Self::SetDuty {
+                    channel,
+                    duty: Duty::new(duty),
+                }
+            }
+            Command::SetRpm => {
+                let (channel, rpm) = channel_value()?;
+                Self::SetRpm {
+                    channel,
+                    velocity: AngularVelocity::from_rpm(rpm),
+                }
+            }
+            Command::SetControlFreq => Self::SetControlFreq {
+                freq: value(payload).filter(|freq| CONTROL_FREQ_RANGE.contains(freq))?,
+            },
+            Command::SetPGain => {
+                let (channel, gain) = channel_value()?;
+                Self::SetPGain { channel, gain }
+            }
+            Command::SetIGain => {
+                let (channel, gain) = channel_value()?;
+                Self::SetIGain { channel, gain }
+            }
+            Command::SetDGain => {
+                let (channel, gain) = channel_value()?;
+                Self::SetDGain { channel, gain }
+            }
+            Command::NotifyRpm => {
+                let (channel, rpm) = channel_value()?;
+                Self::NotifyRpm {
+                    channel,
+                    velocity: AngularVelocity::from_rpm(rpm),
+                }
+            }
+            Command::NotifyFault => match payload {
+                [channel, fault, current @ ..] => Self::NotifyFault {
+                    channel: *channel,
+                    fault: (*fault).into(),
+                    current: value(current)?,
+                },
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+    pub fn from_message<const N: usize>(message: &Message<N>) -> Option<Self> {
+        Self::parse(message.command(), message.payload())
+    }
+    pub fn command(&self) -> Command {
+        match self {
+            Self::Stop { .. } => Command::Stop,
+            Self::SetDuty { .. } => Command::SetDuty,
+            Self::SetRpm { .. } => Command::SetRpm,
+            Self::SetControlFreq { .. } => Command::SetControlFreq,
+            Self::SetPGain { .. } => Command::SetPGain,
+            Self::SetIGain { .. } => Command::SetIGain,
+            Self::SetDGain { .. } => Command::SetDGain,
+            Self::NotifyRpm { .. } => Command::NotifyRpm,
+            Self::NotifyFault { .. } => Command::NotifyFault,
+        }
+    }
+    pub fn payload(&self) -> Vec<u8, 6> {
+        let (channel, value) = match *self {
+            Self::Stop {
+                channel: ALL_CHANNELS,
+            } => return Vec::new(),
+            Self::Stop { channel } => return Vec::from_slice(&[channel]).unwrap(),
+            Self::SetControlFreq { freq } => return Vec::from_slice(&freq.to_be_bytes()).unwrap(),
+            Self::NotifyFault {
+                channel,
+                fault,
+                current,
+            } => {
+                let mut payload = Vec::from_slice(&[channel, fault.into()]).unwrap();
+                payload.extend_from_slice(&current.to_be_bytes()).unwrap();
+                return payload;
+            }
+            Self::SetDuty { channel, duty } => (channel, duty.value()),
+            Self::SetRpm { channel, velocity } | Self::NotifyRpm { channel, velocity } => {
+                (channel, velocity.rpm())
+            }
+            Self::SetPGain { channel, gain }
+            | Self::SetIGain { channel, gain }
+            | Self::SetDGain { channel, gain } => (channel, gain),
+        };
+        let mut payload = Vec::new();
+        payload.push(channel).unwrap();
+        payload.extend_from_slice(&value.to_be_bytes()).unwrap();
+        payload
+    }
+    pub fn into_message<const N: usize>(
+        &self,
+        from: impl Into<Id>,
+        to: impl Into<Id>,
+    ) -> Option<Message<N>> {
+        let payload = Vec::from_slice(&self.payload()).ok()?;
+        Some(Message::new(from, to, self.command(), payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands() -> [MotorCommand; 10] {
+        [
+            MotorCommand::Stop {
+                channel: ALL_CHANNELS,
+            },
+            MotorCommand::Stop { channel: 2 },
+            MotorCommand::SetDuty {
+                channel: 1,
+                duty: Duty::new(-0.5),
+            },
+            MotorCommand::SetRpm {
+                channel: 0,
+                velocity: AngularVelocity::from_rpm(1200.),
+            },
+            MotorCommand::SetControlFreq { freq: 500. },
+            MotorCommand::SetPGain {
+                channel: 0,
+                gain: 0.1,
+            },
+            MotorCommand::SetIGain {
+                channel: 1,
+                gain: 0.2,
+            },
+            MotorCommand::SetDGain {
+                channel: ALL_CHANNELS,
+                gain: 0.3,
+            },
+            MotorCommand::NotifyRpm {
+                channel: 3,
+                velocity: AngularVelocity::from_rpm(-60.),
+            },
+            MotorCommand::NotifyFault {
+                channel: 1,
+                fault: Fault::Stall,
+                current: 12.5,
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trips() {
+        for command in commands() {
+            let parsed = MotorCommand::parse(command.command(), &command.payload()).unwrap();
+            assert_eq!(parsed.command(), command.command());
+            assert_eq!(parsed.payload(), command.payload());
+        }
+    }
+
+    #[test]
+    fn rejects_short_and_long_payloads() {
+        // `Stop` takes either length, which `round_trips` covers.
+        for command in &commands()[2..] {
+            let payload = command.payload();
+            if let Some((_, short)) = payload.split_last() {
+                assert_eq!(MotorCommand::parse(command.command(), short), None);
+            }
+            let mut long: Vec<u8, 8> = Vec::from_slice(&payload).unwrap();
+            long.push(0).unwrap();
+            assert_eq!(MotorCommand::parse(command.command(), &long), None);
+        }
+        assert_eq!(MotorCommand::parse(Command::Ping, &[]), None);
+    }
+
+    #[test]
+    fn rejects_non_finite_values() {
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let mut payload: Vec<u8, 6> = Vec::from_slice(&[0]).unwrap();
+            payload.extend_from_slice(&value.to_be_bytes()).unwrap();
+            for command in [
+                Command::SetDuty,
+                Command::SetRpm,
+                Command::SetPGain,
+                Command::SetIGain,
+                Command::SetDGain,
+                Command::NotifyRpm,
+            ] {
+                assert_eq!(MotorCommand::parse(command, &payload), None);
+            }
+            let mut fault: Vec<u8, 6> = Vec::from_slice(&[0, 1]).unwrap();
+            fault.extend_from_slice(&value.to_be_bytes()).unwrap();
+            assert_eq!(MotorCommand::parse(Command::NotifyFault, &fault), None);
+        }
+    }
+
+    #[test]
+    fn limits_control_freq() {
+        for freq in [
+            f32::NAN,
+            f32::INFINITY,
+            0.,
+            -100.,
+            f32::MIN_POSITIVE / 2.,
+            1e9,
+        ] {
+            assert_eq!(
+                MotorCommand::parse(Command::SetControlFreq, &freq.to_be_bytes()),
+                None
+            );
+        }
+        assert_eq!(
+            MotorCommand::parse(Command::SetControlFreq, &1000f32.to_be_bytes()),
+            Some(MotorCommand::SetControlFreq { freq: 1000. })
+        );
+    }
+}
@@ -0,0 +1,623 @@
+use core::time::Duration;
+
+use heapless::Vec;
+
+use crate::components::{
+    current_sensor::CurrentSensor,
+    motor::{
+        protection::{ProtectedMotor, ProtectedMotorError},
+        Motor,
+    },
+    rotary_encoder::Incremental,
+    velocity_control::VelocityControlledMotor,
+};
+
+use super::{
+    command::Command,
+    id::Id,
+    message::Message,
+    motor_command::{MotorCommand, ALL_CHANNELS},
+    transport::Transport,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum MotorNodeError<M, T> {
+    Motor { channel: u8, error: M },
+    Transport(T),
+}
+
+// Motor driver firmware core. Channels are indexed by their position in the
+// array. Call `poll` whenever messages may have arrived, `update` once every
+// `period()` and `notify_rpm` at whatever rate the feedback is wanted.
+pub struct MotorNode<M: Motor, E: Incremental, T: Transport<N>, const C: usize, const N: usize = 8>
+{
+    id: Id,
+    channels: [VelocityControlledMotor<M, E>; C],
+    // Channels whose motor was cut by a fault, which are left alone until the
+    // fault is cleared.
+    faulted: [bool; C],
+    transport: T,
+}
+
+impl<M: Motor, E: Incremental, T: Transport<N>, const C: usize, const N: usize>
+    MotorNode<M, E, T, C, N>
+{
+    pub fn new(
+        id: impl Into<Id>,
+        channels: [VelocityControlledMotor<M, E>; C],
+        transport: T,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            channels,
+            faulted: [false; C],
+            transport,
+        }
+    }
+    pub fn id(&self) -> Id {
+        self.id
+    }
+    pub fn channel(&self, channel: u8) -> Option<&VelocityControlledMotor<M, E>> {
+        self.channels.get(channel as usize)
+    }
+    // Every channel runs at the same control frequency.
+    pub fn period(&self) -> Duration {
+        self.channels
+            .first()
+            .map_or(Duration::ZERO, VelocityControlledMotor::period)
+    }
+    pub fn poll(&mut self) -> Result<(), MotorNodeError<M::Error, T::Error>> {
+        while let Some(message) = self
+            .transport
+            .receive()
+            .map_err(MotorNodeError::Transport)?
+        {
+            self.handle(&message)?;
+        }
+        Ok(())
+    }
+    // Messages for other nodes, unknown commands, malformed payloads and unknown
+    // channels are ignored.
+    pub fn handle(
+        &mut self,
+        message: &Message<N>,
+    ) -> Result<(), MotorNodeError<M::Error, T::Error>> {
+        if message.to() != self.id && !message.to().is_broadcast() {
+            return Ok(());
+        }
+        if message.command() == Command::Ping {
+            let payload = Vec::from_slice(message.payload()).unwrap();
+            let pong = Message::new(self.id, message.from(), Command::Pong, payload);
+            self.transport
+                .send(pong)
+                .map_err(MotorNodeError::Transport)?;
+            return Ok(());
+        }
+        let Some(command) = MotorCommand::from_message(message) else {
+            return Ok(());
+        };
+        match command {
+            MotorCommand::Stop { channel } => {
+                self.for_channels(channel, VelocityControlledMotor::stop)?
+            }
+            MotorCommand::SetDuty { channel, duty } => {
+                self.channels_mut(channel).for_each(|c| c.set_duty(duty))
+            }
+            MotorCommand::SetRpm { channel, velocity } => self
+                .channels_mut(channel)
+                .for_each(|c| c.set_target_velocity(velocity)),
+            MotorCommand::SetControlFreq { freq } => self
+                .channels_mut(ALL_CHANNELS)
+                .for_each(|c| c.set_control_freq(freq)),
+            MotorCommand::SetPGain { channel, gain } => {
+                self.channels_mut(channel).for_each(|c| c.set_p_gain(gain))
+            }
+            MotorCommand::SetIGain { channel, gain } => {
+                self.channels_mut(channel).for_each(|c| c.set_i_gain(gain))
+            }
+            MotorCommand::SetDGain { channel, gain } => {
+                self.channels_mut(channel).for_each(|c| c.set_d_gain(gain))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    pub fn update(&mut self) -> Result<(), MotorNodeError<M::Error, T::Error>> {
+        self.for_channels(ALL_CHANNELS, VelocityControlledMotor::update)
+    }
+    // Publishes the speed measured by the last `update` of every channel.
+    pub fn notify_rpm(
+        &mut self,
+        to: impl Into<Id>,
+    ) -> Result<(), MotorNodeError<M::Error, T::Error>> {
+        let to = to.into();
+        for (channel, controller) in self.channels.iter().enumerate() {
+            let command = MotorCommand::NotifyRpm {
+                channel: channel as u8,
+                velocity: controller.measured_velocity(),
+            };
+            if let Some(message) = command.into_message(self.id, to) {
+                self.transport
+                    .send(message)
+                    .map_err(MotorNodeError::Transport)?;
+            }
+        }
+        Ok(())
+    }
+    pub fn into_inner(self) -> ([VelocityControlledMotor<M, E>; C], T) {
+        (self.channels, self.transport)
+    }
+    fn channels_mut(
+        &mut self,
+        channel: u8,
+    ) -> impl Iterator<Item = &mut VelocityControlledMotor<M, E>> {
+        self.channels
+            .iter_mut()
+            .enumerate()
+            .filter(move |(index, _)| channel == ALL_CHANNELS || channel as usize == *index)
+            .map(|(_, controller)| controller)
+    }
+    // Runs `f` on every addressed channel that isn't faulted, so one failing motor
+    // doesn't leave the others uncontrolled, and returns the first error.
+    fn for_channels(
+        &mut self,
+        channel: u8,
+        mut f: impl FnMut(&mut VelocityControlledMotor<M, E>) -> Result<(), M::Error>,
+    ) -> Result<(), MotorNodeError<M::Error, T::Error>> {
+        let mut result = Ok(());
+        for (index, controller) in self.channels.iter_mut().enumerate() {
+            if (channel == ALL_CHANNELS || channel as usize == index) && !self.faulted[index] {
+                result = result.and(f(controller).map_err(|error| MotorNodeError::Motor {
+                    channel: index as u8,
+                    error,
+                }));
+            }
+        }
+        result
+    }
+}
+
+type ProtectedNodeError<M, S, T, const N: usize> = MotorNodeError<
+    ProtectedMotorError<<M as Motor>::Error, <S as CurrentSensor>::Error>,
+    <T as Transport<N>>::Error,
+>;
+
+impl<M, S, E, T, const C: usize, const N: usize> MotorNode<ProtectedMotor<M, S>, E, T, C, N>
+where
+    M: Motor,
+    S: CurrentSensor,
+    E: Incremental,
+    T: Transport<N>,
+{
+    // Runs the protection of every channel with the speed measured by the last
+    // `update`, and sends a `NotifyFault` to `to` for every fault that trips. Call
+    // it at the protection's `update_freq`. Channels cut by a fault are skipped by
+    // `update` until `clear_fault`, while the others keep running.
+    pub fn update_protection(
+        &mut self,
+        to: impl Into<Id>,
+    ) -> Result<(), ProtectedNodeError<M, S, T, N>> {
+        let to = to.into();
+        let mut result = Ok(());
+        for (index, controller) in self.channels.iter_mut().enumerate() {
+            let channel = index as u8;
+            let velocity = controller.measured_velocity();
+            let motor = controller.motor_mut();
+            let fault = motor.update(velocity);
+            self.faulted[index] = motor.is_cut();
+            let fault = match fault {
+                Ok(Some(fault)) => fault,
+                Ok(None) => continue,
+                Err(error) => {
+                    result = result.and(Err(MotorNodeError::Motor { channel, error }));
+                    continue;
+                }
+            };
+            let command = MotorCommand::NotifyFault {
+                channel,
+                fault,
+                current: motor.current(),
+            };
+            if let Some(message) = command.into_message(self.id, to) {
+                result = result.and(
+                    self.transport
+                        .send(message)
+                        .map_err(MotorNodeError::Transport),
+                );
+            }
+        }
+        result
+    }
+    // Clears the faults of the addressed channels and stops their controllers, so
+    // they start again from rest.
+    pub fn clear_fault(&mut self, channel: u8) -> Result<(), ProtectedNodeError<M, S, T, N>> {
+        for (index, controller) in self.channels.iter_mut().enumerate() {
+            if channel == ALL_CHANNELS || channel as usize == index {
+                controller.motor_mut().clear_fault();
+                self.faulted[index] = false;
+            }
+        }
+        self.for_channels(channel, VelocityControlledMotor::stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use advanced_pid::PidGain;
+
+    use super::*;
+    use crate::{
+        components::motor::protection::{Fault, FaultAction, ProtectionConfig},
+        mock::{
+            MockCurrentSensor, MockEncoder, MockMotor, MockMotorError, MockTransport, MotorState,
+        },
+        units::{AngularVelocity, Duty},
+    };
+
+    type Node = MotorNode<MockMotor, MockEncoder, MockTransport<8>, 2>;
+
+    const NODE: u8 = 3;
+    const HOST: u8 = 1;
+
+    fn channel(fail: bool) -> VelocityControlledMotor<MockMotor, MockEncoder> {
+        let motor = MockMotor {
+            fail,
+            ..Default::default()
+        };
+        let gain = PidGain {
+            kp: 0.,
+            ki: 0.,
+            kd: 0.,
+        };
+        VelocityControlledMotor::new(motor, MockEncoder::new(1000), gain, 0., 100.)
+    }
+
+    fn node() -> Node {
+        MotorNode::new(
+            NODE,
+            [channel(false), channel(false)],
+            MockTransport::default(),
+        )
+    }
+
+    fn send(node: &mut Node, command: MotorCommand) {
+        node.handle(&command.into_message(HOST, NODE).unwrap())
+            .unwrap();
+    }
+
+    fn send_raw(node: &mut Node, command: Command, payload: &[u8]) {
+        let message = Message::new(HOST, NODE, command, Vec::from_slice(payload).unwrap());
+        node.handle(&message).unwrap();
+    }
+
+    fn motor_states(node: Node) -> [MotorState; 2] {
+        node.into_inner()
+            .0
+            .map(|channel| channel.into_inner().0.state)
+    }
+
+    #[test]
+    fn sets_duty_per_channel() {
+        let mut node = node();
+        let duty = Duty::new(0.5);
+        send(&mut node, MotorCommand::SetDuty { channel: 1, duty });
+        assert!(!node.channel(0).unwrap().is_open_loop());
+        assert!(node.channel(1).unwrap().is_open_loop());
+        node.update().unwrap();
+        assert_eq!(
+            motor_states(node),
+            [MotorState::Speed(Duty::ZERO), MotorState::Speed(duty)]
+        );
+    }
+
+    #[test]
+    fn sets_duty_on_all_channels() {
+        let mut node = node();
+        let duty = Duty::new(-0.25);
+        send(
+            &mut node,
+            MotorCommand::SetDuty {
+                channel: ALL_CHANNELS,
+                duty,
+            },
+        );
+        node.update().unwrap();
+        assert_eq!(motor_states(node), [MotorState::Speed(duty); 2]);
+    }
+
+    #[test]
+    fn sets_rpm() {
+        let mut node = node();
+        let velocity = AngularVelocity::from_rpm(300.);
+        send(
+            &mut node,
+            MotorCommand::SetRpm {
+                channel: 0,
+                velocity,
+            },
+        );
+        assert_eq!(node.channel(0).unwrap().target_velocity(), velocity);
+        assert_eq!(
+            node.channel(1).unwrap().target_velocity(),
+            AngularVelocity::ZERO
+        );
+    }
+
+    #[test]
+    fn stops_channels() {
+        let mut node = node();
+        let duty = Duty::new(0.5);
+        send(
+            &mut node,
+            MotorCommand::SetDuty {
+                channel: ALL_CHANNELS,
+                duty,
+            },
+        );
+        node.update().unwrap();
+        send(&mut node, MotorCommand::Stop { channel: 0 });
+        assert!(!node.channel(0).unwrap().is_open_loop());
+        assert!(node.channel(1).unwrap().is_open_loop());
+
+        send_raw(&mut node, Command::Stop, &[]);
+        assert!(!node.channel(1).unwrap().is_open_loop());
+        assert_eq!(motor_states(node), [MotorState::Brake; 2]);
+    }
+
+    #[test]
+    fn sets_gains() {
+        let mut node = node();
+        send(
+            &mut node,
+            MotorCommand::SetPGain {
+                channel: 0,
+                gain: 1.,
+            },
+        );
+        send(
+            &mut node,
+            MotorCommand::SetIGain {
+                channel: ALL_CHANNELS,
+                gain: 2.,
+            },
+        );
+        send(
+            &mut node,
+            MotorCommand::SetDGain {
+                channel: 1,
+                gain: 3.,
+            },
+        );
+        let gains = [0, 1].map(|i| {
+            let gain = node.channel(i).unwrap().gain();
+            (gain.kp, gain.ki, gain.kd)
+        });
+        assert_eq!(gains, [(1., 2., 0.), (0., 2., 3.)]);
+    }
+
+    #[test]
+    fn sets_control_freq() {
+        let mut node = node();
+        send(&mut node, MotorCommand::SetControlFreq { freq: 200. });
+        assert_eq!(node.period(), Duration::from_millis(5));
+        assert_eq!(node.channel(1).unwrap().control_freq(), 200.);
+    }
+
+    #[test]
+    fn ignores_invalid_control_freq() {
+        let mut node = node();
+        for freq in [f32::INFINITY, f32::NAN, 0., -1., f32::MIN_POSITIVE / 2.] {
+            send_raw(&mut node, Command::SetControlFreq, &freq.to_be_bytes());
+            assert_eq!(node.channel(0).unwrap().control_freq(), 100.);
+        }
+        node.update().unwrap();
+    }
+
+    #[test]
+    fn ignores_malformed_payloads() {
+        let mut node = node();
+        send_raw(&mut node, Command::SetDuty, &[0, 0x3F, 0]);
+        send_raw(&mut node, Command::SetDuty, &[0]);
+        send_raw(&mut node, Command::SetDuty, &[]);
+        let mut nan = [0; 5];
+        nan[1..].copy_from_slice(&f32::NAN.to_be_bytes());
+        send_raw(&mut node, Command::SetDuty, &nan);
+        send_raw(&mut node, Command::SetRpm, &nan);
+        send_raw(&mut node, Command::Stop, &[0, 1]);
+        assert!(!node.channel(0).unwrap().is_open_loop());
+        assert_eq!(
+            node.channel(0).unwrap().target_velocity(),
+            AngularVelocity::ZERO
+        );
+        node.update().unwrap();
+        assert_eq!(motor_states(node), [MotorState::Speed(Duty::ZERO); 2]);
+    }
+
+    #[test]
+    fn ignores_unknown_channels_and_other_nodes() {
+        let mut node = node();
+        let duty = Duty::new(0.5);
+        send(&mut node, MotorCommand::SetDuty { channel: 2, duty });
+        let message = MotorCommand::SetDuty { channel: 0, duty }
+            .into_message(HOST, NODE + 1)
+            .unwrap();
+        node.handle(&message).unwrap();
+        assert!(!node.channel(0).unwrap().is_open_loop());
+        assert!(node.channel(2).is_none());
+
+        let message = MotorCommand::SetDuty { channel: 0, duty }
+            .into_message(HOST, Id::broadcast())
+            .unwrap();
+        node.handle(&message).unwrap();
+        assert!(node.channel(0).unwrap().is_open_loop());
+    }
+
+    #[test]
+    fn answers_ping() {
+        let mut node = node();
+        send_raw(&mut node, Command::Ping, &[1, 2, 3]);
+        let (_, transport) = node.into_inner();
+        let [pong] = &transport.sent[..] else {
+            panic!("expected one pong");
+        };
+        assert_eq!(pong.from(), Id::from(NODE));
+        assert_eq!(pong.to(), Id::from(HOST));
+        assert_eq!(pong.command(), Command::Pong);
+        assert_eq!(pong.payload(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn polls_transport() {
+        let mut node = node();
+        let duty = Duty::new(0.5);
+        let transport = &mut node.transport;
+        for channel in [0, 1] {
+            let command = MotorCommand::SetDuty { channel, duty };
+            transport
+                .received
+                .push_back(command.into_message(HOST, NODE).unwrap());
+        }
+        node.poll().unwrap();
+        assert!(node.transport.received.is_empty());
+        assert!(node.channel(0).unwrap().is_open_loop());
+        assert!(node.channel(1).unwrap().is_open_loop());
+    }
+
+    #[test]
+    fn notifies_rpm() {
+        let mut node = node();
+        node.update().unwrap();
+        node.notify_rpm(HOST).unwrap();
+        let (_, transport) = node.into_inner();
+        assert_eq!(transport.sent.len(), 2);
+        for (channel, message) in transport.sent.iter().enumerate() {
+            assert_eq!(message.to(), Id::from(HOST));
+            assert_eq!(
+                MotorCommand::from_message(message),
+                Some(MotorCommand::NotifyRpm {
+                    channel: channel as u8,
+                    velocity: AngularVelocity::ZERO,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn reports_failing_channel() {
+        let channels = [channel(false), channel(true)];
+        let mut node: Node = MotorNode::new(NODE, channels, MockTransport::default());
+        assert_eq!(
+            node.update(),
+            Err(MotorNodeError::Motor {
+                channel: 1,
+                error: MockMotorError
+            })
+        );
+    }
+
+    #[test]
+    fn runs_channels_after_failing_one() {
+        let channels = [channel(true), channel(false)];
+        let mut node: Node = MotorNode::new(NODE, channels, MockTransport::default());
+        let duty = Duty::new(0.5);
+        send(&mut node, MotorCommand::SetDuty { channel: 1, duty });
+        for _ in 0..3 {
+            assert_eq!(
+                node.update(),
+                Err(MotorNodeError::Motor {
+                    channel: 0,
+                    error: MockMotorError
+                })
+            );
+        }
+        let [failing, running] = node.into_inner().0.map(|c| c.into_inner().0);
+        assert_eq!(failing.calls, 3);
+        assert_eq!(running.calls, 3);
+        assert_eq!(running.state, MotorState::Speed(duty));
+    }
+
+    fn protected_channel(
+        current: f32,
+    ) -> VelocityControlledMotor<ProtectedMotor<MockMotor, MockCurrentSensor>, MockEncoder> {
+        let config = ProtectionConfig {
+            filter: 0.5,
+            max_current: 10.,
+            stall_current: f32::INFINITY,
+            stall_velocity: AngularVelocity::ZERO,
+            stall_time: Duration::from_secs(1),
+            rated_current: 10.,
+            i2t_limit: f32::INFINITY,
+            action: FaultAction::Cut,
+            update_freq: 100.,
+        };
+        let sensor = MockCurrentSensor { current };
+        let motor = ProtectedMotor::new(MockMotor::default(), sensor, config);
+        let gain = PidGain {
+            kp: 0.,
+            ki: 0.,
+            kd: 0.,
+        };
+        VelocityControlledMotor::new(motor, MockEncoder::new(1000), gain, 0., 100.)
+    }
+
+    #[test]
+    fn notifies_faults() {
+        let mut node = MotorNode::<_, _, MockTransport<8>, 2>::new(
+            NODE,
+            [protected_channel(1.), protected_channel(12.)],
+            MockTransport::default(),
+        );
+        node.update_protection(HOST).unwrap();
+        node.update_protection(HOST).unwrap();
+        let (_, transport) = node.into_inner();
+        let [message] = &transport.sent[..] else {
+            panic!("expected one fault");
+        };
+        assert_eq!(message.to(), Id::from(HOST));
+        assert_eq!(
+            MotorCommand::from_message(message),
+            Some(MotorCommand::NotifyFault {
+                channel: 1,
+                fault: Fault::Overcurrent,
+                current: 6.,
+            })
+        );
+    }
+
+    #[test]
+    fn runs_healthy_channels_after_fault() {
+        let mut node = MotorNode::<_, _, MockTransport<8>, 2>::new(
+            NODE,
+            [protected_channel(12.), protected_channel(1.)],
+            MockTransport::default(),
+        );
+        let duty = Duty::new(0.5);
+        let message = MotorCommand::SetDuty {
+            channel: ALL_CHANNELS,
+            duty,
+        }
+        .into_message(HOST, NODE)
+        .unwrap();
+        node.handle(&message).unwrap();
+        for _ in 0..3 {
+            node.update().unwrap();
+            node.update_protection(HOST).unwrap();
+        }
+        assert_eq!(node.transport.sent.len(), 1);
+
+        // Once cleared, the channel runs again until the fault trips anew.
+        node.clear_fault(0).unwrap();
+        node.update().unwrap();
+        node.update_protection(HOST).unwrap();
+        assert_eq!(node.transport.sent.len(), 2);
+
+        let (channels, _) = node.into_inner();
+        let [faulted, healthy] = channels.map(|c| c.into_inner().0.into_inner().0);
+        // Set and cut, braked on clearing, then set and cut again. `update` left it
+        // alone in between.
+        assert_eq!(faulted.calls, 5);
+        assert_eq!(faulted.state, MotorState::Coast);
+        assert_eq!(healthy.calls, 4);
+        assert_eq!(healthy.state, MotorState::Speed(duty));
+    }
+}
@@ -0,0 +1,22 @@
+use core::cell::RefCell;
+
+use super::message::Message;
+
+// A message link a node is polled over, e.g. a CAN peripheral or an SBTP serial
+// link. `receive` must not block and returns `None` when nothing is pending.
+pub trait Transport<const N: usize> {
+    type Error;
+    fn send(&mut self, message: Message<N>) -> Result<(), Self::Error>;
+    fn receive(&mut self) -> Result<Option<Message<N>>, Self::Error>;
+}
+
+// Lets several users, e.g. a `RemoteMotor` per wheel, share one bus.
+impl<T: Transport<N>, const N: usize> Transport<N> for &RefCell<T> {
+    type Error = T::Error;
+    fn send(&mut self, message: Message<N>) -> Result<(), Self::Error> {
+        self.borrow_mut().send(message)
+    }
+    fn receive(&mut self) -> Result<Option<Message<N>>, Self::Error> {
+        self.borrow_mut().receive()
+    }
+}
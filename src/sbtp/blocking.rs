@@ -0,0 +1,96 @@
+use embedded_io::{Read, Write};
+use heapless::Vec;
+
+use crate::node::message::Message;
+
+use super::{
+    fragment::{Fragments, Reassembler},
+    frame::{Framing, SBTP_PAYLOAD_MAX_SIZE},
+    stats::{link_stats_methods, Link},
+    Error,
+};
+
+pub struct Sbtp<IO: Read + Write> {
+    pub(super) transport: IO,
+    link: Link,
+    next_message_id: u8,
+}
+
+impl<IO: Read + Write> Sbtp<IO> {
+    pub fn new(transport: IO) -> Self {
+        Self::with_framing(transport, Framing::default())
+    }
+    pub fn with_framing(transport: IO, framing: Framing) -> Self {
+        Self {
+            transport,
+            link: Link::new(framing),
+            next_message_id: 0,
+        }
+    }
+    link_stats_methods!();
+    pub fn send(&mut self, data: &[u8]) -> Result<(), Error<IO::Error>> {
+        self.send_parts(&[data])
+    }
+    pub fn send_message<const N: usize>(
+        &mut self,
+        message: &Message<N>,
+    ) -> Result<(), Error<IO::Error>> {
+        self.send_parts(&[&message.header(), message.payload()])
+    }
+    pub fn receive(&mut self) -> Result<Vec<u8, SBTP_PAYLOAD_MAX_SIZE>, Error<IO::Error>> {
+        let mut receiver = self.link.receiver();
+
+        loop {
+            if let Some(payload) = receiver.push(read_byte(&mut self.transport)?)? {
+                return Ok(payload);
+            }
+        }
+    }
+    pub fn send_fragmented(&mut self, data: &[u8]) -> Result<(), Error<IO::Error>> {
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+
+        for (header, chunk) in Fragments::new(message_id, data)? {
+            self.send_parts(&[&header.into_array(), chunk])?;
+        }
+
+        Ok(())
+    }
+    pub fn receive_fragmented<'a, const N: usize>(
+        &mut self,
+        reassembler: &'a mut Reassembler<N>,
+    ) -> Result<&'a [u8], Error<IO::Error>> {
+        loop {
+            let payload = self.receive()?;
+            if reassembler.push(&payload)?.is_some() {
+                return Ok(reassembler.message());
+            }
+        }
+    }
+    fn send_parts(&mut self, parts: &[&[u8]]) -> Result<(), Error<IO::Error>> {
+        let mut encoder = self.link.encoder(parts)?;
+
+        while let Some(chunk) = encoder.next_chunk() {
+            if let Err(e) = self.transport.write_all(chunk) {
+                return Err(Error::TransportError(e));
+            }
+            self.link.record_sent(chunk.len());
+        }
+        self.link.record_frame_sent();
+
+        Ok(())
+    }
+}
+
+fn read_byte<R: Read>(transport: &mut R) -> Result<u8, Error<R::Error>> {
+    let mut buf = [0u8];
+    match transport.read(&mut buf) {
+        Ok(len) => {
+            if len != 1 {
+                return Err(Error::Eof);
+            }
+            Ok(buf[0])
+        }
+        Err(e) => Err(Error::TransportError(e)),
+    }
+}
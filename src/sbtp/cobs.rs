@@ -0,0 +1,287 @@
+use heapless::Vec;
+
+use super::frame::{crc8, Crc8, Decoded, FrameError, Parts, SBTP_PAYLOAD_MAX_SIZE};
+
+const COBS_DELIMITER: u8 = 0x00;
+const COBS_BLOCK_MAX_SIZE: usize = 0xFF;
+
+const ENCODER_CHUNK_SIZE: usize = 32;
+
+#[derive(Clone)]
+struct Source<'a> {
+    parts: Parts<'a>,
+    crc: Option<u8>,
+}
+
+impl Iterator for Source<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parts.next().or_else(|| self.crc.take())
+    }
+}
+
+// A COBS frame is COBS(payload ++ crc8(payload)) followed by a single delimiter.
+// Blocks are found by scanning ahead in the borrowed payload, so only a small
+// output chunk is buffered.
+pub(super) struct CobsEncoder<'a> {
+    source: Source<'a>,
+    // Data bytes left in the current block, and whether a zero byte (or the end of
+    // the frame) follows it.
+    remaining: u8,
+    block_open: bool,
+    done: bool,
+    buf: [u8; ENCODER_CHUNK_SIZE],
+}
+
+impl<'a> CobsEncoder<'a> {
+    pub(super) fn new(parts: Parts<'a>) -> Self {
+        let mut crc = Crc8::new();
+        for d in parts.clone() {
+            crc.update(d);
+        }
+        Self {
+            source: Source {
+                parts,
+                crc: Some(crc.finish()),
+            },
+            remaining: 0,
+            block_open: false,
+            done: false,
+            buf: [0; ENCODER_CHUNK_SIZE],
+        }
+    }
+    pub(super) fn next_chunk(&mut self) -> Option<&[u8]> {
+        let mut n = 0;
+
+        while n < ENCODER_CHUNK_SIZE && !self.done {
+            if self.remaining > 0 {
+                self.buf[n] = self.source.next().unwrap();
+                self.remaining -= 1;
+                n += 1;
+                continue;
+            }
+
+            if self.block_open {
+                // Consume the zero byte the block stands for, or finish the frame.
+                self.block_open = false;
+                if self.source.next().is_none() {
+                    self.buf[n] = COBS_DELIMITER;
+                    n += 1;
+                    self.done = true;
+                }
+                continue;
+            }
+
+            let len = self
+                .source
+                .clone()
+                .take(COBS_BLOCK_MAX_SIZE - 1)
+                .take_while(|d| *d != COBS_DELIMITER)
+                .count();
+            self.buf[n] = len as u8 + 1;
+            n += 1;
+            self.remaining = len as u8;
+            // A maximum size block is not followed by an implicit zero, unless it is
+            // the last block of the frame.
+            self.block_open =
+                len < COBS_BLOCK_MAX_SIZE - 1 || self.source.clone().nth(len).is_none();
+        }
+
+        if n == 0 {
+            None
+        } else {
+            Some(&self.buf[..n])
+        }
+    }
+}
+
+pub(super) struct CobsDecoder {
+    buf: Vec<u8, { SBTP_PAYLOAD_MAX_SIZE + 1 }>,
+    code: u8,
+    remaining: u8,
+    skip: bool,
+}
+
+impl CobsDecoder {
+    pub(super) fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            code: 0,
+            remaining: 0,
+            skip: false,
+        }
+    }
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.code = 0;
+        self.remaining = 0;
+        self.skip = false;
+    }
+    pub(super) fn push(&mut self, byte: u8) -> Result<Decoded, FrameError> {
+        if byte == COBS_DELIMITER {
+            let result = self.finish();
+            self.reset();
+            return result;
+        }
+        if self.skip {
+            return Ok(Decoded::Dropped);
+        }
+
+        let pushed = if self.remaining == 0 {
+            // Every block shorter than the maximum stands for a zero byte, unless it
+            // is the last block of the frame.
+            let pushed = if self.code != 0 && self.code as usize != COBS_BLOCK_MAX_SIZE {
+                self.buf.push(0)
+            } else {
+                Ok(())
+            };
+            self.code = byte;
+            self.remaining = byte - 1;
+            pushed
+        } else {
+            self.remaining -= 1;
+            self.buf.push(byte)
+        };
+
+        if pushed.is_err() {
+            self.skip = true;
+            return Err(FrameError::InvalidFormat);
+        }
+        Ok(Decoded::Pending)
+    }
+    fn finish(&mut self) -> Result<Decoded, FrameError> {
+        if self.skip || self.code == 0 {
+            // Resynchronized after an error, or an empty frame between two delimiters.
+            return Ok(Decoded::Dropped);
+        }
+        if self.remaining != 0 {
+            return Err(FrameError::InvalidFormat);
+        }
+        let Some((crc, payload)) = self.buf.split_last() else {
+            return Err(FrameError::InvalidFormat);
+        };
+        if *crc != crc8(payload) {
+            return Err(FrameError::Crc);
+        }
+        Ok(Decoded::Frame(Vec::from_slice(payload).unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sbtp::frame::SBTP_COBS_FRAME_MAX_SIZE;
+
+    fn encode(data: &[u8]) -> Vec<u8, SBTP_COBS_FRAME_MAX_SIZE> {
+        let parts: &[&[u8]] = &[data];
+        let mut encoder = CobsEncoder::new(Parts::new(parts).unwrap());
+        let mut buf = Vec::new();
+        while let Some(chunk) = encoder.next_chunk() {
+            buf.extend_from_slice(chunk).unwrap();
+        }
+        buf
+    }
+
+    fn decode(frame: &[u8]) -> Result<Vec<u8, SBTP_PAYLOAD_MAX_SIZE>, FrameError> {
+        let mut decoder = CobsDecoder::new();
+        for byte in frame {
+            match decoder.push(*byte)? {
+                Decoded::Frame(payload) => return Ok(payload),
+                Decoded::Pending | Decoded::Dropped => {}
+            }
+        }
+        panic!("frame not finished");
+    }
+
+    fn round_trip(data: &[u8]) {
+        let frame = encode(data);
+        let (delimiter, body) = frame.split_last().unwrap();
+        assert_eq!(*delimiter, COBS_DELIMITER);
+        assert!(!body.contains(&COBS_DELIMITER));
+        // One code byte per started block of 254, plus the CRC.
+        assert!(frame.len() <= data.len() + 1 + (data.len() + 1) / 254 + 2);
+        assert_eq!(&decode(&frame).unwrap()[..], data);
+    }
+
+    fn non_zero(len: usize) -> Vec<u8, SBTP_PAYLOAD_MAX_SIZE> {
+        (0..len).map(|i| (i % 255) as u8 + 1).collect()
+    }
+
+    #[test]
+    fn encodes_known_frame() {
+        let crc = crc8(&[0x11, 0x00, 0x22]);
+        assert_ne!(crc, 0);
+        assert_eq!(
+            &encode(&[0x11, 0x00, 0x22])[..],
+            &[0x02, 0x11, 0x03, 0x22, crc, 0x00]
+        );
+    }
+
+    #[test]
+    fn round_trips_empty_payload() {
+        round_trip(&[]);
+    }
+
+    #[test]
+    fn round_trips_zeros() {
+        round_trip(&[0]);
+        round_trip(&[0; 16]);
+        round_trip(&[0; SBTP_PAYLOAD_MAX_SIZE]);
+        round_trip(&[1, 2, 3, 0, 0]);
+        round_trip(&[0, 0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn round_trips_long_runs() {
+        for len in [253, 254, 255] {
+            round_trip(&non_zero(len));
+        }
+        for len in [253, 254] {
+            let mut data = non_zero(len);
+            data.push(0).unwrap();
+            round_trip(&data);
+
+            let mut data: Vec<u8, SBTP_PAYLOAD_MAX_SIZE> = Vec::from_slice(&[0]).unwrap();
+            data.extend_from_slice(&non_zero(len)).unwrap();
+            round_trip(&data);
+        }
+    }
+
+    #[test]
+    fn rejects_code_past_delimiter() {
+        let mut frame = encode(&[1, 2, 3]);
+        frame[0] = 0x10;
+        assert_eq!(decode(&frame), Err(FrameError::InvalidFormat));
+    }
+
+    #[test]
+    fn rejects_corrupt_code() {
+        let mut frame = encode(&[0x11, 0x00, 0x22]);
+        // The block now swallows the next code byte as data.
+        frame[0] = 0x03;
+        assert_eq!(decode(&frame), Err(FrameError::InvalidFormat));
+
+        let mut frame = encode(&[0x11, 0x00, 0x22, 0x33]);
+        // The block now ends early and inserts a spurious zero.
+        frame[2] = 0x02;
+        assert!(decode(&frame).is_err());
+    }
+
+    #[test]
+    fn resynchronizes_after_error() {
+        let mut decoder = CobsDecoder::new();
+        let mut bytes: Vec<u8, 64> = Vec::from_slice(&[0x05, 0x01, 0x00]).unwrap();
+        bytes.extend_from_slice(&encode(&[4, 5, 6])).unwrap();
+        let mut results = bytes.iter().map(|b| decoder.push(*b));
+        assert!(results.by_ref().take(2).all(|r| r.is_ok()));
+        assert_eq!(
+            results.next().unwrap().err(),
+            Some(FrameError::InvalidFormat)
+        );
+        match results.last() {
+            Some(Ok(Decoded::Frame(payload))) => assert_eq!(&payload[..], &[4, 5, 6]),
+            _ => panic!("frame not decoded"),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use core::mem;
+
+use heapless::Vec;
+
+use super::frame::{crc8, Crc8, Decoded, FrameError, Parts, SBTP_PAYLOAD_MAX_SIZE};
+
+const SBTP_SOF_BYTE: u8 = 0x55;
+const SBTP_ESCAPE_BYTE: u8 = 0x5A;
+const SBTP_EOF_BYTE: u8 = 0xAA;
+const SBTP_XOR_BYTE: u8 = 0x42;
+
+fn needs_escape(byte: u8) -> bool {
+    byte == SBTP_SOF_BYTE || byte == SBTP_EOF_BYTE || byte == SBTP_ESCAPE_BYTE
+}
+
+const ENCODER_CHUNK_SIZE: usize = 32;
+
+#[derive(PartialEq)]
+enum Stage {
+    Header,
+    Payload,
+    Trailer,
+    Done,
+}
+
+// Emits the escaped frame in small chunks, so it can be written out without ever
+// holding the whole frame in memory.
+pub(super) struct EscapeEncoder<'a> {
+    parts: Parts<'a>,
+    len: u8,
+    crc: Crc8,
+    stage: Stage,
+    buf: [u8; ENCODER_CHUNK_SIZE],
+}
+
+impl<'a> EscapeEncoder<'a> {
+    pub(super) fn new(parts: Parts<'a>) -> Self {
+        Self {
+            len: parts.len() as u8,
+            parts,
+            crc: Crc8::new(),
+            stage: Stage::Header,
+            buf: [0; ENCODER_CHUNK_SIZE],
+        }
+    }
+    pub(super) fn next_chunk(&mut self) -> Option<&[u8]> {
+        let mut n = 0;
+
+        // Every step emits at most two bytes.
+        while n + 2 <= ENCODER_CHUNK_SIZE && self.stage != Stage::Done {
+            match self.stage {
+                Stage::Header => {
+                    self.buf[n] = SBTP_SOF_BYTE;
+                    self.buf[n + 1] = self.len;
+                    n += 2;
+                    self.stage = Stage::Payload;
+                }
+                Stage::Payload => {
+                    let Some(d) = self.parts.next() else {
+                        self.stage = Stage::Trailer;
+                        continue;
+                    };
+                    self.crc.update(d);
+                    if needs_escape(d) {
+                        self.buf[n] = SBTP_ESCAPE_BYTE;
+                        self.buf[n + 1] = d ^ SBTP_XOR_BYTE;
+                        n += 2;
+                    } else {
+                        self.buf[n] = d;
+                        n += 1;
+                    }
+                }
+                Stage::Trailer => {
+                    self.buf[n] = self.crc.finish();
+                    self.buf[n + 1] = SBTP_EOF_BYTE;
+                    n += 2;
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => unreachable!(),
+            }
+        }
+
+        if n == 0 {
+            None
+        } else {
+            Some(&self.buf[..n])
+        }
+    }
+}
+
+enum State {
+    Sof,
+    Len,
+    Payload,
+    Crc,
+    Eof,
+}
+
+pub(super) struct EscapeDecoder {
+    state: State,
+    len: u8,
+    escaped: bool,
+    crc: u8,
+    payload: Vec<u8, SBTP_PAYLOAD_MAX_SIZE>,
+}
+
+impl EscapeDecoder {
+    pub(super) fn new() -> Self {
+        Self {
+            state: State::Sof,
+            len: 0,
+            escaped: false,
+            crc: 0,
+            payload: Vec::new(),
+        }
+    }
+    pub(super) fn push(&mut self, byte: u8) -> Result<Decoded, FrameError> {
+        match self.state {
+            State::Sof => {
+                if byte != SBTP_SOF_BYTE {
+                    return Ok(Decoded::Dropped);
+                }
+                self.payload.clear();
+                self.escaped = false;
+                self.state = State::Len;
+            }
+            State::Len => {
+                self.len = byte;
+                self.state = if byte == 0 {
+                    State::Crc
+                } else {
+                    State::Payload
+                };
+            }
+            State::Payload => {
+                if self.escaped {
+                    self.escaped = false;
+                    self.payload.push(byte ^ SBTP_XOR_BYTE).unwrap();
+                } else if byte == SBTP_ESCAPE_BYTE {
+                    self.escaped = true;
+                } else {
+                    self.payload.push(byte).unwrap();
+                }
+                if self.payload.len() == self.len as usize {
+                    self.state = State::Crc;
+                }
+            }
+            State::Crc => {
+                self.crc = byte;
+                self.state = State::Eof;
+            }
+            State::Eof => {
+                self.state = State::Sof;
+                if byte != SBTP_EOF_BYTE {
+                    return Err(FrameError::InvalidFormat);
+                }
+                if self.crc != crc8(&self.payload) {
+                    return Err(FrameError::Crc);
+                }
+                return Ok(Decoded::Frame(mem::take(&mut self.payload)));
+            }
+        }
+        Ok(Decoded::Pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sbtp::frame::SBTP_FRAME_MAX_SIZE;
+
+    // The original one-shot encoder, kept as the reference for the wire format.
+    fn encode(data: &[u8]) -> Vec<u8, SBTP_FRAME_MAX_SIZE> {
+        let mut buf = Vec::new();
+        buf.push(SBTP_SOF_BYTE).unwrap();
+        buf.push(data.len() as u8).unwrap();
+        for d in data {
+            if needs_escape(*d) {
+                buf.push(SBTP_ESCAPE_BYTE).unwrap();
+                buf.push(*d ^ SBTP_XOR_BYTE).unwrap();
+            } else {
+                buf.push(*d).unwrap();
+            }
+        }
+        buf.push(crc8(data)).unwrap();
+        buf.push(SBTP_EOF_BYTE).unwrap();
+        buf
+    }
+
+    fn stream(parts: &[&[u8]]) -> Vec<u8, SBTP_FRAME_MAX_SIZE> {
+        let mut encoder = EscapeEncoder::new(Parts::new(parts).ok().unwrap());
+        let mut buf = Vec::new();
+        while let Some(chunk) = encoder.next_chunk() {
+            assert!(chunk.len() <= ENCODER_CHUNK_SIZE);
+            buf.extend_from_slice(chunk).unwrap();
+        }
+        buf
+    }
+
+    fn payloads() -> [Vec<u8, SBTP_PAYLOAD_MAX_SIZE>; 5] {
+        [
+            Vec::new(),
+            Vec::from_slice(&[1, 2, 3]).unwrap(),
+            (0..=254).collect(),
+            core::iter::repeat_n(SBTP_SOF_BYTE, 255).collect(),
+            [SBTP_EOF_BYTE, SBTP_ESCAPE_BYTE, 0, SBTP_SOF_BYTE]
+                .into_iter()
+                .cycle()
+                .take(255)
+                .collect(),
+        ]
+    }
+
+    #[test]
+    fn streams_same_bytes_as_one_shot() {
+        for data in payloads() {
+            assert_eq!(stream(&[&data]), encode(&data));
+        }
+    }
+
+    #[test]
+    fn parts_are_sent_back_to_back() {
+        for data in payloads() {
+            for split in [0, 1.min(data.len()), data.len() / 2, data.len()] {
+                let (head, tail) = data.split_at(split);
+                assert_eq!(stream(&[head, &[], tail]), encode(&data));
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_streamed_frame() {
+        for data in payloads() {
+            let mut decoder = EscapeDecoder::new();
+            let frame = stream(&[&data]);
+            let (last, rest) = frame.split_last().unwrap();
+            for byte in rest {
+                assert!(matches!(decoder.push(*byte), Ok(Decoded::Pending)));
+            }
+            match decoder.push(*last) {
+                Ok(Decoded::Frame(payload)) => assert_eq!(payload, data),
+                _ => panic!("frame not decoded"),
+            }
+        }
+    }
+}
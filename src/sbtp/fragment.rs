@@ -0,0 +1,307 @@
+use core::{fmt, slice::Chunks};
+
+use heapless::Vec;
+
+use super::frame::SBTP_PAYLOAD_MAX_SIZE;
+
+const FRAGMENT_HEADER_SIZE: usize = 3;
+pub const FRAGMENT_CHUNK_MAX_SIZE: usize = SBTP_PAYLOAD_MAX_SIZE - FRAGMENT_HEADER_SIZE;
+pub const FRAGMENTED_MESSAGE_MAX_SIZE: usize = FRAGMENT_CHUNK_MAX_SIZE * u8::MAX as usize;
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FragmentError {
+    InvalidHeader,
+    UnexpectedFragment,
+    MessageOverflow,
+}
+
+impl fmt::Display for FragmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FragmentError::InvalidHeader => f.write_str("invalid fragment header"),
+            FragmentError::UnexpectedFragment => f.write_str("fragment out of sequence"),
+            FragmentError::MessageOverflow => f.write_str("message exceeds the maximum size"),
+        }
+    }
+}
+
+// Every fragment starts with [message id, fragment index, total fragment count].
+#[derive(Debug, PartialEq)]
+pub struct FragmentHeader {
+    pub message_id: u8,
+    pub index: u8,
+    pub total: u8,
+}
+
+impl FragmentHeader {
+    pub fn parse(fragment: &[u8]) -> Result<(Self, &[u8]), FragmentError> {
+        let (header, chunk) = fragment
+            .split_first_chunk::<FRAGMENT_HEADER_SIZE>()
+            .ok_or(FragmentError::InvalidHeader)?;
+        let [message_id, index, total] = *header;
+        if total == 0 || index >= total {
+            return Err(FragmentError::InvalidHeader);
+        }
+        Ok((
+            Self {
+                message_id,
+                index,
+                total,
+            },
+            chunk,
+        ))
+    }
+    pub fn into_array(&self) -> [u8; FRAGMENT_HEADER_SIZE] {
+        [self.message_id, self.index, self.total]
+    }
+}
+
+pub struct Fragments<'a> {
+    message_id: u8,
+    index: u8,
+    total: u8,
+    chunks: Chunks<'a, u8>,
+}
+
+impl<'a> Fragments<'a> {
+    pub fn new(message_id: u8, data: &'a [u8]) -> Result<Self, FragmentError> {
+        if data.len() > FRAGMENTED_MESSAGE_MAX_SIZE {
+            return Err(FragmentError::MessageOverflow);
+        }
+        // An empty message is still sent as a single, empty fragment.
+        let total = data.len().div_ceil(FRAGMENT_CHUNK_MAX_SIZE).max(1) as u8;
+        Ok(Self {
+            message_id,
+            index: 0,
+            total,
+            chunks: data.chunks(FRAGMENT_CHUNK_MAX_SIZE),
+        })
+    }
+}
+
+impl<'a> Iterator for Fragments<'a> {
+    type Item = (FragmentHeader, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.total {
+            return None;
+        }
+        let chunk = self.chunks.next().unwrap_or(&[]);
+        let header = FragmentHeader {
+            message_id: self.message_id,
+            index: self.index,
+            total: self.total,
+        };
+        self.index += 1;
+        Some((header, chunk))
+    }
+}
+
+pub struct Reassembler<const N: usize> {
+    buf: Vec<u8, N>,
+    message_id: u8,
+    next_index: u8,
+    total: u8,
+}
+
+impl<const N: usize> Default for Reassembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Reassembler<N> {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            message_id: 0,
+            next_index: 0,
+            total: 0,
+        }
+    }
+    pub fn is_idle(&self) -> bool {
+        self.total == 0
+    }
+    pub fn message(&self) -> &[u8] {
+        &self.buf
+    }
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.next_index = 0;
+        self.total = 0;
+    }
+    pub fn push(&mut self, fragment: &[u8]) -> Result<Option<&[u8]>, FragmentError> {
+        let (header, chunk) = FragmentHeader::parse(fragment)?;
+
+        if header.index == 0 {
+            self.reset();
+            self.message_id = header.message_id;
+            self.total = header.total;
+        } else if self.is_idle()
+            || header.message_id != self.message_id
+            || header.total != self.total
+            || header.index != self.next_index
+        {
+            self.reset();
+            return Err(FragmentError::UnexpectedFragment);
+        }
+
+        if self.buf.extend_from_slice(chunk).is_err() {
+            self.reset();
+            return Err(FragmentError::MessageOverflow);
+        }
+        self.next_index += 1;
+
+        if self.next_index == self.total {
+            self.next_index = 0;
+            self.total = 0;
+            return Ok(Some(&self.buf));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragments(message_id: u8, data: &[u8]) -> heapless::Vec<Vec<u8, 255>, 8> {
+        Fragments::new(message_id, data)
+            .unwrap()
+            .map(|(header, chunk)| {
+                let mut fragment = Vec::from_slice(&header.into_array()).unwrap();
+                fragment.extend_from_slice(chunk).unwrap();
+                fragment
+            })
+            .collect()
+    }
+
+    fn message(len: usize) -> Vec<u8, 1024> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn splits_into_chunks() {
+        let data = message(FRAGMENT_CHUNK_MAX_SIZE * 2 + 10);
+        let fragments = fragments(7, &data);
+        assert_eq!(fragments.len(), 3);
+        for (index, fragment) in fragments.iter().enumerate() {
+            let (header, _) = FragmentHeader::parse(fragment).unwrap();
+            assert_eq!(
+                header,
+                FragmentHeader {
+                    message_id: 7,
+                    index: index as u8,
+                    total: 3
+                }
+            );
+        }
+        assert_eq!(fragments[2].len(), FRAGMENT_HEADER_SIZE + 10);
+    }
+
+    #[test]
+    fn empty_message_is_one_fragment() {
+        let fragments = fragments(1, &[]);
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(&fragments[0][..], &[1, 0, 1]);
+
+        let mut reassembler = Reassembler::<16>::new();
+        assert_eq!(reassembler.push(&fragments[0]), Ok(Some(&[][..])));
+    }
+
+    #[test]
+    fn rejects_oversized_message() {
+        let data = [0; FRAGMENTED_MESSAGE_MAX_SIZE + 1];
+        assert!(matches!(
+            Fragments::new(0, &data),
+            Err(FragmentError::MessageOverflow)
+        ));
+    }
+
+    #[test]
+    fn reassembles_in_order() {
+        let data = message(600);
+        let mut reassembler = Reassembler::<1024>::new();
+        let fragments = fragments(3, &data);
+        for fragment in &fragments[..2] {
+            assert_eq!(reassembler.push(fragment), Ok(None));
+            assert!(!reassembler.is_idle());
+        }
+        assert_eq!(reassembler.push(&fragments[2]), Ok(Some(&data[..])));
+        assert!(reassembler.is_idle());
+    }
+
+    #[test]
+    fn rejects_out_of_order_fragment() {
+        let data = message(600);
+        let fragments = fragments(3, &data);
+        let mut reassembler = Reassembler::<1024>::new();
+        reassembler.push(&fragments[0]).unwrap();
+        assert_eq!(
+            reassembler.push(&fragments[2]),
+            Err(FragmentError::UnexpectedFragment)
+        );
+        assert!(reassembler.is_idle());
+    }
+
+    #[test]
+    fn rejects_missing_first_fragment() {
+        let data = message(600);
+        let fragments = fragments(3, &data);
+        let mut reassembler = Reassembler::<1024>::new();
+        assert_eq!(
+            reassembler.push(&fragments[1]),
+            Err(FragmentError::UnexpectedFragment)
+        );
+    }
+
+    #[test]
+    fn restarts_on_new_message() {
+        let first = message(600);
+        let second = message(10);
+        let mut reassembler = Reassembler::<1024>::new();
+        reassembler.push(&fragments(1, &first)[0]).unwrap();
+        // The rest of message 1 was lost; message 2 starts over.
+        let fragments = fragments(2, &second);
+        assert_eq!(reassembler.push(&fragments[0]), Ok(Some(&second[..])));
+    }
+
+    #[test]
+    fn rejects_fragment_of_other_message() {
+        let data = message(600);
+        let mut reassembler = Reassembler::<1024>::new();
+        reassembler.push(&fragments(1, &data)[0]).unwrap();
+        assert_eq!(
+            reassembler.push(&fragments(2, &data)[1]),
+            Err(FragmentError::UnexpectedFragment)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_header() {
+        let mut reassembler = Reassembler::<16>::new();
+        assert_eq!(reassembler.push(&[0, 0]), Err(FragmentError::InvalidHeader));
+        assert_eq!(
+            reassembler.push(&[0, 0, 0]),
+            Err(FragmentError::InvalidHeader)
+        );
+        assert_eq!(
+            reassembler.push(&[0, 2, 2]),
+            Err(FragmentError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn reports_overflow() {
+        let data = message(600);
+        let fragments = fragments(1, &data);
+        let mut reassembler = Reassembler::<300>::new();
+        reassembler.push(&fragments[0]).unwrap();
+        assert_eq!(
+            reassembler.push(&fragments[1]),
+            Err(FragmentError::MessageOverflow)
+        );
+        assert!(reassembler.is_idle());
+    }
+}
@@ -0,0 +1,160 @@
+use heapless::Vec;
+
+use super::{
+    cobs::{CobsDecoder, CobsEncoder},
+    escape::{EscapeDecoder, EscapeEncoder},
+};
+
+const GENERATE_POLYNOMIAL: u8 = 0xD5;
+const INITIAL_VALUE: u8 = 0xFF;
+
+pub const SBTP_FRAME_MAX_SIZE: usize = u8::MAX as usize * 2 + 4;
+pub const SBTP_COBS_FRAME_MAX_SIZE: usize = u8::MAX as usize + 4;
+pub const SBTP_PAYLOAD_MAX_SIZE: usize = u8::MAX as usize;
+
+pub(super) struct Crc8(u8);
+
+impl Crc8 {
+    pub(super) fn new() -> Self {
+        Self(INITIAL_VALUE)
+    }
+    pub(super) fn update(&mut self, data: u8) {
+        self.0 ^= data;
+        for _ in 0..8 {
+            if self.0 & 0x80 != 0 {
+                self.0 = (self.0 << 1) ^ GENERATE_POLYNOMIAL;
+            } else {
+                self.0 <<= 1;
+            }
+        }
+    }
+    pub(super) fn finish(&self) -> u8 {
+        self.0 ^ 0xFF
+    }
+}
+
+pub(super) fn crc8(data: &[u8]) -> u8 {
+    let mut crc = Crc8::new();
+    for d in data {
+        crc.update(*d);
+    }
+    crc.finish()
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) enum FrameError {
+    PayloadOverflow,
+    InvalidFormat,
+    Crc,
+}
+
+// Escape is the original SBTP wire format. COBS bounds the encoding overhead to
+// one byte per 254, at the cost of not being understood by older boards.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Framing {
+    #[default]
+    Escape,
+    Cobs,
+}
+
+// The payload of a frame, given as several slices that are sent back to back.
+#[derive(Clone)]
+pub(super) struct Parts<'a> {
+    parts: &'a [&'a [u8]],
+    part: usize,
+    offset: usize,
+}
+
+impl<'a> Parts<'a> {
+    pub(super) fn new(parts: &'a [&'a [u8]]) -> Result<Self, FrameError> {
+        if parts.iter().map(|p| p.len()).sum::<usize>() > SBTP_PAYLOAD_MAX_SIZE {
+            return Err(FrameError::PayloadOverflow);
+        }
+        Ok(Self {
+            parts,
+            part: 0,
+            offset: 0,
+        })
+    }
+    pub(super) fn len(&self) -> usize {
+        self.parts.iter().map(|p| p.len()).sum()
+    }
+}
+
+impl Iterator for Parts<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let part = self.parts.get(self.part)?;
+            if let Some(&d) = part.get(self.offset) {
+                self.offset += 1;
+                return Some(d);
+            }
+            self.part += 1;
+            self.offset = 0;
+        }
+    }
+}
+
+pub(super) enum Encoder<'a> {
+    Escape(EscapeEncoder<'a>),
+    Cobs(CobsEncoder<'a>),
+}
+
+impl<'a> Encoder<'a> {
+    pub(super) fn new(framing: Framing, parts: &'a [&'a [u8]]) -> Result<Self, FrameError> {
+        let parts = Parts::new(parts)?;
+        Ok(match framing {
+            Framing::Escape => Encoder::Escape(EscapeEncoder::new(parts)),
+            Framing::Cobs => Encoder::Cobs(CobsEncoder::new(parts)),
+        })
+    }
+    pub(super) fn next_chunk(&mut self) -> Option<&[u8]> {
+        match self {
+            Encoder::Escape(encoder) => encoder.next_chunk(),
+            Encoder::Cobs(encoder) => encoder.next_chunk(),
+        }
+    }
+}
+
+pub fn encode_into(framing: Framing, parts: &[&[u8]], buf: &mut [u8]) -> Option<usize> {
+    let mut encoder = Encoder::new(framing, parts).ok()?;
+    let mut len = 0;
+    while let Some(chunk) = encoder.next_chunk() {
+        buf.get_mut(len..len + chunk.len())?.copy_from_slice(chunk);
+        len += chunk.len();
+    }
+    Some(len)
+}
+
+// Frames are moved straight out to the caller, so the size difference doesn't matter.
+#[allow(clippy::large_enum_variant)]
+pub(super) enum Decoded {
+    // The byte is not part of any frame, e.g. line noise before a start of frame.
+    Dropped,
+    Pending,
+    Frame(Vec<u8, SBTP_PAYLOAD_MAX_SIZE>),
+}
+
+// Byte-at-a-time frame parser shared by the async and blocking transports.
+pub(super) enum Decoder {
+    Escape(EscapeDecoder),
+    Cobs(CobsDecoder),
+}
+
+impl Decoder {
+    pub(super) fn new(framing: Framing) -> Self {
+        match framing {
+            Framing::Escape => Decoder::Escape(EscapeDecoder::new()),
+            Framing::Cobs => Decoder::Cobs(CobsDecoder::new()),
+        }
+    }
+    pub(super) fn push(&mut self, byte: u8) -> Result<Decoded, FrameError> {
+        match self {
+            Decoder::Escape(decoder) => decoder.push(byte),
+            Decoder::Cobs(decoder) => decoder.push(byte),
+        }
+    }
+}
@@ -0,0 +1,403 @@
+pub mod blocking;
+mod cobs;
+mod escape;
+pub mod fragment;
+pub mod frame;
+pub mod split;
+pub mod stats;
+
+use core::fmt;
+
+use embedded_io::ErrorKind;
+use embedded_io_async::{Read, Write};
+use heapless::Vec;
+
+use crate::node::message::Message;
+use fragment::{FragmentError, Fragments, Reassembler};
+use frame::{FrameError, Framing, SBTP_PAYLOAD_MAX_SIZE};
+use split::{SbtpReader, SbtpWriter, Split};
+use stats::{link_stats_methods, Link};
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    PayloadOverflow,
+    InvalidFormat,
+    Crc,
+    Fragment(FragmentError),
+    // The transport returned 0 bytes, i.e. the peer is gone.
+    Eof,
+    TransportError(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PayloadOverflow => f.write_str("payload exceeds the SBTP frame size"),
+            Error::InvalidFormat => f.write_str("malformed SBTP frame"),
+            Error::Crc => f.write_str("SBTP CRC mismatch"),
+            Error::Fragment(e) => write!(f, "SBTP fragment error: {}", e),
+            Error::Eof => f.write_str("SBTP transport reached end of file"),
+            Error::TransportError(e) => write!(f, "SBTP transport error: {:?}", e),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for Error<E> {}
+
+impl<E: embedded_io::Error> embedded_io::Error for Error<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::PayloadOverflow => ErrorKind::InvalidInput,
+            Error::InvalidFormat | Error::Crc | Error::Fragment(_) => ErrorKind::InvalidData,
+            Error::Eof => ErrorKind::BrokenPipe,
+            Error::TransportError(e) => e.kind(),
+        }
+    }
+}
+
+impl<E: embedded_io::Error> From<Error<E>> for ErrorKind {
+    fn from(value: Error<E>) -> Self {
+        embedded_io::Error::kind(&value)
+    }
+}
+
+impl<E> From<FrameError> for Error<E> {
+    fn from(value: FrameError) -> Self {
+        match value {
+            FrameError::PayloadOverflow => Error::PayloadOverflow,
+            FrameError::InvalidFormat => Error::InvalidFormat,
+            FrameError::Crc => Error::Crc,
+        }
+    }
+}
+
+impl<E> From<FragmentError> for Error<E> {
+    fn from(value: FragmentError) -> Self {
+        Error::Fragment(value)
+    }
+}
+
+pub struct Sbtp<IO: Read + Write> {
+    transport: IO,
+    link: Link,
+    next_message_id: u8,
+}
+
+impl<IO: Read + Write> Sbtp<IO> {
+    pub fn new(transport: IO) -> Self {
+        Self::with_framing(transport, Framing::default())
+    }
+    pub fn with_framing(transport: IO, framing: Framing) -> Self {
+        Self {
+            transport,
+            link: Link::new(framing),
+            next_message_id: 0,
+        }
+    }
+    link_stats_methods!();
+    // Each half keeps the statistics of its own direction; add both halves' stats
+    // to get the figures for the whole link.
+    pub fn split(self) -> (SbtpReader<IO::Reader>, SbtpWriter<IO::Writer>)
+    where
+        IO: Split,
+    {
+        let (reader, writer) = self.transport.split();
+        let (reader_link, writer_link) = self.link.split();
+        (
+            SbtpReader {
+                transport: reader,
+                link: reader_link,
+            },
+            SbtpWriter {
+                transport: writer,
+                link: writer_link,
+                next_message_id: self.next_message_id,
+            },
+        )
+    }
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), Error<IO::Error>> {
+        send(&mut self.transport, &mut self.link, data).await
+    }
+    pub async fn send_message<const N: usize>(
+        &mut self,
+        message: &Message<N>,
+    ) -> Result<(), Error<IO::Error>> {
+        send_message(&mut self.transport, &mut self.link, message).await
+    }
+    pub async fn receive(&mut self) -> Result<Vec<u8, SBTP_PAYLOAD_MAX_SIZE>, Error<IO::Error>> {
+        receive(&mut self.transport, &mut self.link).await
+    }
+    pub async fn send_fragmented(&mut self, data: &[u8]) -> Result<(), Error<IO::Error>> {
+        send_fragmented(
+            &mut self.transport,
+            &mut self.link,
+            &mut self.next_message_id,
+            data,
+        )
+        .await
+    }
+    pub async fn receive_fragmented<'a, const N: usize>(
+        &mut self,
+        reassembler: &'a mut Reassembler<N>,
+    ) -> Result<&'a [u8], Error<IO::Error>> {
+        receive_fragmented(&mut self.transport, &mut self.link, reassembler).await
+    }
+}
+
+async fn send<W: Write>(
+    transport: &mut W,
+    link: &mut Link,
+    data: &[u8],
+) -> Result<(), Error<W::Error>> {
+    send_parts(transport, link, &[data]).await
+}
+
+async fn send_message<W: Write, const N: usize>(
+    transport: &mut W,
+    link: &mut Link,
+    message: &Message<N>,
+) -> Result<(), Error<W::Error>> {
+    send_parts(transport, link, &[&message.header(), message.payload()]).await
+}
+
+async fn send_parts<W: Write>(
+    transport: &mut W,
+    link: &mut Link,
+    parts: &[&[u8]],
+) -> Result<(), Error<W::Error>> {
+    let mut encoder = link.encoder(parts)?;
+
+    while let Some(chunk) = encoder.next_chunk() {
+        if let Err(e) = transport.write_all(chunk).await {
+            return Err(Error::TransportError(e));
+        }
+        link.record_sent(chunk.len());
+    }
+    link.record_frame_sent();
+
+    Ok(())
+}
+
+async fn receive<R: Read>(
+    transport: &mut R,
+    link: &mut Link,
+) -> Result<Vec<u8, SBTP_PAYLOAD_MAX_SIZE>, Error<R::Error>> {
+    let mut receiver = link.receiver();
+
+    loop {
+        if let Some(payload) = receiver.push(read_byte(transport).await?)? {
+            return Ok(payload);
+        }
+    }
+}
+
+async fn send_fragmented<W: Write>(
+    transport: &mut W,
+    link: &mut Link,
+    next_message_id: &mut u8,
+    data: &[u8],
+) -> Result<(), Error<W::Error>> {
+    let message_id = *next_message_id;
+    *next_message_id = next_message_id.wrapping_add(1);
+
+    for (header, chunk) in Fragments::new(message_id, data)? {
+        send_parts(transport, link, &[&header.into_array(), chunk]).await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_fragmented<'a, R: Read, const N: usize>(
+    transport: &mut R,
+    link: &mut Link,
+    reassembler: &'a mut Reassembler<N>,
+) -> Result<&'a [u8], Error<R::Error>> {
+    loop {
+        let payload = receive(transport, link).await?;
+        if reassembler.push(&payload)?.is_some() {
+            return Ok(reassembler.message());
+        }
+    }
+}
+
+async fn read_byte<R: Read>(transport: &mut R) -> Result<u8, Error<R::Error>> {
+    let mut buf = [0u8];
+    match transport.read(&mut buf).await {
+        Ok(len) => {
+            if len != 1 {
+                return Err(Error::Eof);
+            }
+            Ok(buf[0])
+        }
+        Err(e) => Err(Error::TransportError(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock::{block_on, Pipe},
+        node::command::Command,
+    };
+    use stats::LinkStats;
+
+    const FRAMINGS: [Framing; 2] = [Framing::Escape, Framing::Cobs];
+
+    // Exercises the escape and delimiter bytes of both framings.
+    const DATA: &[u8] = &[0x00, 0x7E, 0x7D, 0x01, 0xFF, 0x00, 0x7E];
+
+    fn message() -> Message<8> {
+        Message::new(1, 2, Command::Ping, Vec::from_slice(DATA).unwrap())
+    }
+
+    fn fragmented_data() -> Vec<u8, 600> {
+        (0..600).map(|i| i as u8).collect()
+    }
+
+    fn blocking_bytes(framing: Framing) -> std::vec::Vec<u8> {
+        let mut sbtp = blocking::Sbtp::with_framing(Pipe::new(), framing);
+        sbtp.send(DATA).unwrap();
+        sbtp.send_message(&message()).unwrap();
+        sbtp.send_fragmented(&fragmented_data()).unwrap();
+        sbtp.transport.bytes()
+    }
+
+    fn async_bytes(framing: Framing) -> std::vec::Vec<u8> {
+        let mut sbtp = Sbtp::with_framing(Pipe::new(), framing);
+        block_on(async {
+            sbtp.send(DATA).await.unwrap();
+            sbtp.send_message(&message()).await.unwrap();
+            sbtp.send_fragmented(&fragmented_data()).await.unwrap();
+        });
+        sbtp.transport.bytes()
+    }
+
+    fn pipe(bytes: &[u8]) -> Pipe {
+        Pipe {
+            data: bytes.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn blocking_and_async_send_identical_bytes() {
+        for framing in FRAMINGS {
+            assert_eq!(blocking_bytes(framing), async_bytes(framing));
+        }
+    }
+
+    #[test]
+    fn async_receives_blocking_frames() {
+        for framing in FRAMINGS {
+            let mut sbtp = Sbtp::with_framing(pipe(&blocking_bytes(framing)), framing);
+            let mut reassembler = Reassembler::<1024>::new();
+            block_on(async {
+                assert_eq!(&sbtp.receive().await.unwrap()[..], DATA);
+                let payload = sbtp.receive().await.unwrap();
+                assert_eq!(&payload[..3], &message().header()[..]);
+                assert_eq!(&payload[3..], DATA);
+                assert_eq!(
+                    sbtp.receive_fragmented(&mut reassembler).await.unwrap(),
+                    &fragmented_data()[..]
+                );
+                assert_eq!(sbtp.receive().await, Err(Error::Eof));
+            });
+        }
+    }
+
+    #[test]
+    fn blocking_receives_async_frames() {
+        for framing in FRAMINGS {
+            let mut sbtp = blocking::Sbtp::with_framing(pipe(&async_bytes(framing)), framing);
+            let mut reassembler = Reassembler::<1024>::new();
+            assert_eq!(&sbtp.receive().unwrap()[..], DATA);
+            let payload = sbtp.receive().unwrap();
+            assert_eq!(&payload[..3], &message().header()[..]);
+            assert_eq!(&payload[3..], DATA);
+            assert_eq!(
+                sbtp.receive_fragmented(&mut reassembler).unwrap(),
+                &fragmented_data()[..]
+            );
+            assert_eq!(sbtp.receive(), Err(Error::Eof));
+        }
+    }
+
+    #[test]
+    fn displays_debug_only_transport_error() {
+        #[derive(Debug)]
+        struct Uart;
+
+        let error: Error<Uart> = Error::TransportError(Uart);
+        assert_eq!(std::format!("{}", error), "SBTP transport error: Uart");
+        let _: &dyn core::error::Error = &error;
+    }
+
+    // Reads from one pipe and writes to another, like the two lines of a UART.
+    #[derive(Default)]
+    struct Duplex {
+        rx: Pipe,
+        tx: Pipe,
+    }
+
+    impl embedded_io_async::ErrorType for Duplex {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Read for Duplex {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.rx.read(buf).await
+        }
+    }
+
+    impl Write for Duplex {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.tx.write(buf).await
+        }
+    }
+
+    impl Split for Duplex {
+        type Reader = Pipe;
+        type Writer = Pipe;
+        fn split(self) -> (Pipe, Pipe) {
+            (self.rx, self.tx)
+        }
+    }
+
+    #[test]
+    fn split_halves_keep_their_own_stats() {
+        let mut sbtp = Sbtp::new(Duplex {
+            rx: pipe(&blocking_bytes(Framing::Escape)),
+            tx: Pipe::new(),
+        });
+        block_on(async {
+            sbtp.send(DATA).await.unwrap();
+            sbtp.send(DATA).await.unwrap();
+            sbtp.receive().await.unwrap();
+        });
+        let stats = sbtp.stats();
+        assert_eq!(stats.frames_sent, 2);
+        assert_eq!(stats.frames_received, 1);
+
+        let (mut reader, mut writer) = sbtp.split();
+        assert_eq!(reader.stats().frames_sent, 0);
+        assert_eq!(reader.stats().bytes_sent, 0);
+        assert_eq!(reader.stats().frames_received, 1);
+        assert_eq!(writer.stats().frames_sent, 2);
+        assert_eq!(writer.stats().frames_received, 0);
+        assert_eq!(writer.stats().bytes_received, 0);
+        assert_eq!(reader.stats() + writer.stats(), stats);
+
+        block_on(async {
+            reader.receive().await.unwrap();
+            writer.send(DATA).await.unwrap();
+        });
+        assert_eq!(reader.stats().frames_received, 2);
+        assert_eq!(reader.stats().frames_sent, 0);
+        assert_eq!(writer.stats().frames_sent, 3);
+        assert_eq!(writer.stats().frames_received, 0);
+
+        writer.reset_stats();
+        assert_eq!(writer.stats(), LinkStats::default());
+    }
+}
@@ -0,0 +1,84 @@
+use embedded_io_async::{Read, Write};
+use heapless::Vec;
+
+use crate::node::message::Message;
+
+use super::{
+    fragment::Reassembler,
+    frame::{Framing, SBTP_PAYLOAD_MAX_SIZE},
+    stats::{link_stats_methods, Link},
+    Error,
+};
+
+// Implemented by transports that can hand out independent read and write halves,
+// e.g. a UART split into its RX and TX parts.
+pub trait Split {
+    type Reader: Read;
+    type Writer: Write;
+    fn split(self) -> (Self::Reader, Self::Writer);
+}
+
+pub struct SbtpReader<R: Read> {
+    pub(super) transport: R,
+    pub(super) link: Link,
+}
+
+impl<R: Read> SbtpReader<R> {
+    pub fn new(transport: R) -> Self {
+        Self::with_framing(transport, Framing::default())
+    }
+    pub fn with_framing(transport: R, framing: Framing) -> Self {
+        Self {
+            transport,
+            link: Link::new(framing),
+        }
+    }
+    link_stats_methods!();
+    pub async fn receive(&mut self) -> Result<Vec<u8, SBTP_PAYLOAD_MAX_SIZE>, Error<R::Error>> {
+        super::receive(&mut self.transport, &mut self.link).await
+    }
+    pub async fn receive_fragmented<'a, const N: usize>(
+        &mut self,
+        reassembler: &'a mut Reassembler<N>,
+    ) -> Result<&'a [u8], Error<R::Error>> {
+        super::receive_fragmented(&mut self.transport, &mut self.link, reassembler).await
+    }
+}
+
+pub struct SbtpWriter<W: Write> {
+    pub(super) transport: W,
+    pub(super) link: Link,
+    pub(super) next_message_id: u8,
+}
+
+impl<W: Write> SbtpWriter<W> {
+    pub fn new(transport: W) -> Self {
+        Self::with_framing(transport, Framing::default())
+    }
+    pub fn with_framing(transport: W, framing: Framing) -> Self {
+        Self {
+            transport,
+            link: Link::new(framing),
+            next_message_id: 0,
+        }
+    }
+    link_stats_methods!();
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), Error<W::Error>> {
+        super::send(&mut self.transport, &mut self.link, data).await
+    }
+    pub async fn send_message<const N: usize>(
+        &mut self,
+        message: &Message<N>,
+    ) -> Result<(), Error<W::Error>> {
+        super::send_message(&mut self.transport, &mut self.link, message).await
+    }
+    pub async fn send_fragmented(&mut self, data: &[u8]) -> Result<(), Error<W::Error>> {
+        super::send_fragmented(
+            &mut self.transport,
+            &mut self.link,
+            &mut self.next_message_id,
+            data,
+        )
+        .await
+    }
+}
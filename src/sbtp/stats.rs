@@ -0,0 +1,223 @@
+use core::{ops::Add, time::Duration};
+
+use heapless::Vec;
+
+use crate::node::{command::Command, id::Id, message::Message};
+
+use super::frame::{Decoded, Decoder, Encoder, FrameError, Framing, SBTP_PAYLOAD_MAX_SIZE};
+
+// Monotonic time since boot, used to measure how long a frame takes to arrive.
+pub type Clock = fn() -> Duration;
+
+const LINK_STATS_SIZE: usize = 36;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkStats {
+    pub frames_sent: u32,
+    pub frames_received: u32,
+    pub bytes_sent: u32,
+    pub bytes_received: u32,
+    pub crc_errors: u32,
+    pub framing_errors: u32,
+    pub resyncs: u32,
+    pub dropped_bytes: u32,
+    pub max_frame_latency: Duration,
+}
+
+impl LinkStats {
+    pub fn into_array(&self) -> [u8; LINK_STATS_SIZE] {
+        let latency_us = self.max_frame_latency.as_micros().min(u32::MAX.into()) as u32;
+        let mut array = [0; LINK_STATS_SIZE];
+        for (bytes, value) in array.chunks_exact_mut(4).zip([
+            self.frames_sent,
+            self.frames_received,
+            self.bytes_sent,
+            self.bytes_received,
+            self.crc_errors,
+            self.framing_errors,
+            self.resyncs,
+            self.dropped_bytes,
+            latency_us,
+        ]) {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+        array
+    }
+    // Returns None if the 36-byte payload doesn't fit in `N`, which is always the
+    // case for a `CanMessage` but not for an `EspNowMessage`.
+    pub fn into_message<const N: usize>(
+        &self,
+        from: impl Into<Id>,
+        to: impl Into<Id>,
+    ) -> Option<Message<N>> {
+        let payload = Vec::from_slice(&self.into_array()).ok()?;
+        Some(Message::new(from, to, Command::NotifyLinkStats, payload))
+    }
+    // Separates the counters of a link into its receiving and sending halves.
+    pub(super) fn split(self) -> (Self, Self) {
+        let sent = Self {
+            frames_sent: self.frames_sent,
+            bytes_sent: self.bytes_sent,
+            ..Self::default()
+        };
+        let received = Self {
+            frames_sent: 0,
+            bytes_sent: 0,
+            ..self
+        };
+        (received, sent)
+    }
+}
+
+impl From<&[u8; LINK_STATS_SIZE]> for LinkStats {
+    fn from(value: &[u8; LINK_STATS_SIZE]) -> Self {
+        let mut values = value
+            .chunks_exact(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()));
+        let mut next = || values.next().unwrap();
+        Self {
+            frames_sent: next(),
+            frames_received: next(),
+            bytes_sent: next(),
+            bytes_received: next(),
+            crc_errors: next(),
+            framing_errors: next(),
+            resyncs: next(),
+            dropped_bytes: next(),
+            max_frame_latency: Duration::from_micros(next().into()),
+        }
+    }
+}
+
+// Combines the statistics of the two halves of a split link.
+impl Add for LinkStats {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            frames_sent: self.frames_sent.wrapping_add(rhs.frames_sent),
+            frames_received: self.frames_received.wrapping_add(rhs.frames_received),
+            bytes_sent: self.bytes_sent.wrapping_add(rhs.bytes_sent),
+            bytes_received: self.bytes_received.wrapping_add(rhs.bytes_received),
+            crc_errors: self.crc_errors.wrapping_add(rhs.crc_errors),
+            framing_errors: self.framing_errors.wrapping_add(rhs.framing_errors),
+            resyncs: self.resyncs.wrapping_add(rhs.resyncs),
+            dropped_bytes: self.dropped_bytes.wrapping_add(rhs.dropped_bytes),
+            max_frame_latency: self.max_frame_latency.max(rhs.max_frame_latency),
+        }
+    }
+}
+
+// The statistics accessors of every transport that owns a `Link`.
+macro_rules! link_stats_methods {
+    () => {
+        pub fn set_clock(&mut self, clock: $crate::sbtp::stats::Clock) {
+            self.link.clock = Some(clock);
+        }
+        pub fn stats(&self) -> $crate::sbtp::stats::LinkStats {
+            self.link.stats
+        }
+        pub fn reset_stats(&mut self) {
+            self.link.stats = $crate::sbtp::stats::LinkStats::default();
+        }
+    };
+}
+pub(super) use link_stats_methods;
+
+// Per-link state shared by the async, split and blocking transports.
+pub(super) struct Link {
+    pub(super) framing: Framing,
+    pub(super) stats: LinkStats,
+    pub(super) clock: Option<Clock>,
+}
+
+impl Link {
+    pub(super) fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            stats: LinkStats::default(),
+            clock: None,
+        }
+    }
+    pub(super) fn split(self) -> (Self, Self) {
+        let (received, sent) = self.stats.split();
+        (
+            Self {
+                stats: received,
+                ..self
+            },
+            Self {
+                stats: sent,
+                ..self
+            },
+        )
+    }
+    pub(super) fn encoder<'a>(&self, parts: &'a [&'a [u8]]) -> Result<Encoder<'a>, FrameError> {
+        Encoder::new(self.framing, parts)
+    }
+    pub(super) fn record_sent(&mut self, len: usize) {
+        self.stats.bytes_sent = self.stats.bytes_sent.wrapping_add(len as u32);
+    }
+    pub(super) fn record_frame_sent(&mut self) {
+        self.stats.frames_sent = self.stats.frames_sent.wrapping_add(1);
+    }
+    pub(super) fn receiver(&mut self) -> Receiver<'_> {
+        Receiver {
+            decoder: Decoder::new(self.framing),
+            link: self,
+            started: None,
+            dropping: false,
+        }
+    }
+}
+
+pub(super) struct Receiver<'a> {
+    link: &'a mut Link,
+    decoder: Decoder,
+    started: Option<Duration>,
+    dropping: bool,
+}
+
+impl Receiver<'_> {
+    pub(super) fn push(
+        &mut self,
+        byte: u8,
+    ) -> Result<Option<Vec<u8, SBTP_PAYLOAD_MAX_SIZE>>, FrameError> {
+        let stats = &mut self.link.stats;
+        stats.bytes_received = stats.bytes_received.wrapping_add(1);
+
+        match self.decoder.push(byte) {
+            Ok(Decoded::Dropped) => {
+                stats.dropped_bytes = stats.dropped_bytes.wrapping_add(1);
+                if !self.dropping {
+                    self.dropping = true;
+                    stats.resyncs = stats.resyncs.wrapping_add(1);
+                }
+                Ok(None)
+            }
+            Ok(Decoded::Pending) => {
+                self.dropping = false;
+                if self.started.is_none() {
+                    self.started = self.link.clock.map(|clock| clock());
+                }
+                Ok(None)
+            }
+            Ok(Decoded::Frame(payload)) => {
+                self.dropping = false;
+                stats.frames_received = stats.frames_received.wrapping_add(1);
+                if let (Some(started), Some(clock)) = (self.started.take(), self.link.clock) {
+                    let latency = clock().saturating_sub(started);
+                    stats.max_frame_latency = stats.max_frame_latency.max(latency);
+                }
+                Ok(Some(payload))
+            }
+            Err(e) => {
+                self.started = None;
+                match e {
+                    FrameError::Crc => stats.crc_errors = stats.crc_errors.wrapping_add(1),
+                    _ => stats.framing_errors = stats.framing_errors.wrapping_add(1),
+                }
+                Err(e)
+            }
+        }
+    }
+}
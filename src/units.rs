@@ -0,0 +1,368 @@
+use core::{
+    f32::consts::{PI, TAU},
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+    time::Duration,
+};
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+// Each quantity wraps an `f32` in SI units. Values only get in and out through
+// the named constructors and getters, so units can't be mixed up silently.
+// Arithmetic builds its results with `$new`, which lets `Duty` clamp them.
+macro_rules! quantity {
+    ($name:ident) => {
+        quantity!($name, Self);
+    };
+    ($name:ident, $new:path) => {
+        #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        pub struct $name(f32);
+
+        impl $name {
+            pub const ZERO: Self = Self(0.);
+            pub fn abs(self) -> Self {
+                Self(self.0.abs())
+            }
+            pub fn clamp(self, min: Self, max: Self) -> Self {
+                Self(self.0.clamp(min.0, max.0))
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self::Output {
+                $new(self.0 + rhs.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = $new(self.0 + rhs.0);
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self::Output {
+                $new(self.0 - rhs.0)
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = $new(self.0 - rhs.0);
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+            fn neg(self) -> Self::Output {
+                Self(-self.0)
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = Self;
+            fn mul(self, rhs: f32) -> Self::Output {
+                $new(self.0 * rhs)
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = Self;
+            fn div(self, rhs: f32) -> Self::Output {
+                $new(self.0 / rhs)
+            }
+        }
+
+        // The ratio of two quantities of the same kind.
+        impl Div for $name {
+            type Output = f32;
+            fn div(self, rhs: Self) -> Self::Output {
+                self.0 / rhs.0
+            }
+        }
+    };
+}
+
+quantity!(Angle);
+quantity!(AngularVelocity);
+quantity!(AngularAcceleration);
+quantity!(Length);
+quantity!(LinearVelocity);
+quantity!(Duty, Duty::new);
+
+impl Angle {
+    pub fn from_radians(radians: f32) -> Self {
+        Self(radians)
+    }
+    pub fn from_degrees(degrees: f32) -> Self {
+        Self(degrees.to_radians())
+    }
+    pub fn from_rotations(rotations: f32) -> Self {
+        Self(rotations * TAU)
+    }
+    pub fn radians(self) -> f32 {
+        self.0
+    }
+    pub fn degrees(self) -> f32 {
+        self.0.to_degrees()
+    }
+    pub fn rotations(self) -> f32 {
+        self.0 / TAU
+    }
+    // Wraps into -π..π, e.g. for the error between two headings.
+    pub fn normalized(self) -> Self {
+        Self((self.0 + PI).rem_euclid(TAU) - PI)
+    }
+}
+
+impl AngularVelocity {
+    pub fn from_radians_per_second(radians_per_second: f32) -> Self {
+        Self(radians_per_second)
+    }
+    pub fn from_rotations_per_second(rotations_per_second: f32) -> Self {
+        Self(rotations_per_second * TAU)
+    }
+    pub fn from_rpm(rpm: f32) -> Self {
+        Self(rpm * TAU / 60.)
+    }
+    pub fn radians_per_second(self) -> f32 {
+        self.0
+    }
+    pub fn rotations_per_second(self) -> f32 {
+        self.0 / TAU
+    }
+    pub fn rpm(self) -> f32 {
+        self.0 * 60. / TAU
+    }
+}
+
+impl AngularAcceleration {
+    pub fn from_radians_per_second_squared(radians_per_second_squared: f32) -> Self {
+        Self(radians_per_second_squared)
+    }
+    pub fn from_rotations_per_second_squared(rotations_per_second_squared: f32) -> Self {
+        Self(rotations_per_second_squared * TAU)
+    }
+    pub fn radians_per_second_squared(self) -> f32 {
+        self.0
+    }
+    pub fn rotations_per_second_squared(self) -> f32 {
+        self.0 / TAU
+    }
+}
+
+impl Length {
+    pub fn from_meters(meters: f32) -> Self {
+        Self(meters)
+    }
+    pub fn from_millimeters(millimeters: f32) -> Self {
+        Self(millimeters / 1000.)
+    }
+    pub fn meters(self) -> f32 {
+        self.0
+    }
+    pub fn millimeters(self) -> f32 {
+        self.0 * 1000.
+    }
+}
+
+impl LinearVelocity {
+    pub fn from_meters_per_second(meters_per_second: f32) -> Self {
+        Self(meters_per_second)
+    }
+    pub fn from_millimeters_per_second(millimeters_per_second: f32) -> Self {
+        Self(millimeters_per_second / 1000.)
+    }
+    pub fn meters_per_second(self) -> f32 {
+        self.0
+    }
+    pub fn millimeters_per_second(self) -> f32 {
+        self.0 * 1000.
+    }
+}
+
+// Normalized signed motor command: 1.0 is full power clockwise, -1.0 full power
+// counterclockwise. Constructors clamp to that range.
+impl Duty {
+    pub const MAX: Self = Self(1.);
+    pub const MIN: Self = Self(-1.);
+    pub fn new(duty: f32) -> Self {
+        Self(duty.clamp(-1., 1.))
+    }
+    pub fn from_percent(percent: f32) -> Self {
+        Self::new(percent / 100.)
+    }
+    // Maps a joystick axis, -128..=127, to -1.0..=1.0.
+    pub fn from_axis(axis: i8) -> Self {
+        Self::new(axis as f32 / i8::MAX as f32)
+    }
+    pub fn value(self) -> f32 {
+        self.0
+    }
+    pub fn percent(self) -> f32 {
+        self.0 * 100.
+    }
+}
+
+impl Mul<Duration> for AngularVelocity {
+    type Output = Angle;
+    fn mul(self, rhs: Duration) -> Self::Output {
+        Angle(self.0 * rhs.as_secs_f32())
+    }
+}
+
+impl Div<Duration> for Angle {
+    type Output = AngularVelocity;
+    fn div(self, rhs: Duration) -> Self::Output {
+        AngularVelocity(self.0 / rhs.as_secs_f32())
+    }
+}
+
+impl Mul<Duration> for AngularAcceleration {
+    type Output = AngularVelocity;
+    fn mul(self, rhs: Duration) -> Self::Output {
+        AngularVelocity(self.0 * rhs.as_secs_f32())
+    }
+}
+
+impl Div<Duration> for AngularVelocity {
+    type Output = AngularAcceleration;
+    fn div(self, rhs: Duration) -> Self::Output {
+        AngularAcceleration(self.0 / rhs.as_secs_f32())
+    }
+}
+
+impl Mul<Duration> for LinearVelocity {
+    type Output = Length;
+    fn mul(self, rhs: Duration) -> Self::Output {
+        Length(self.0 * rhs.as_secs_f32())
+    }
+}
+
+impl Div<Duration> for Length {
+    type Output = LinearVelocity;
+    fn div(self, rhs: Duration) -> Self::Output {
+        LinearVelocity(self.0 / rhs.as_secs_f32())
+    }
+}
+
+// Surface speed of a wheel of radius `rhs`.
+impl Mul<Length> for AngularVelocity {
+    type Output = LinearVelocity;
+    fn mul(self, rhs: Length) -> Self::Output {
+        LinearVelocity(self.0 * rhs.0)
+    }
+}
+
+// Angular velocity of a wheel of radius `rhs` rolling at this speed.
+impl Div<Length> for LinearVelocity {
+    type Output = AngularVelocity;
+    fn div(self, rhs: Length) -> Self::Output {
+        AngularVelocity(self.0 / rhs.0)
+    }
+}
+
+// Arc length along a circle of radius `rhs`.
+impl Mul<Length> for Angle {
+    type Output = Length;
+    fn mul(self, rhs: Length) -> Self::Output {
+        Length(self.0 * rhs.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!((value - expected).abs() < 1e-5, "{value} != {expected}");
+    }
+
+    #[test]
+    fn converts_angular_velocity() {
+        let velocity = AngularVelocity::from_rpm(60.);
+        assert_close(velocity.radians_per_second(), TAU);
+        assert_close(velocity.rotations_per_second(), 1.);
+        assert_close(AngularVelocity::from_rpm(-1234.5).rpm(), -1234.5);
+        assert_close(AngularVelocity::from_rotations_per_second(2.5).rpm(), 150.);
+    }
+
+    #[test]
+    fn converts_angular_acceleration() {
+        let acceleration = AngularAcceleration::from_rotations_per_second_squared(2.);
+        assert_close(acceleration.radians_per_second_squared(), 2. * TAU);
+        assert_close(acceleration.rotations_per_second_squared(), 2.);
+        let velocity = acceleration * Duration::from_millis(500);
+        assert_close(velocity.rotations_per_second(), 1.);
+        assert_close(
+            (velocity / Duration::from_secs(2)).rotations_per_second_squared(),
+            0.5,
+        );
+    }
+
+    #[test]
+    fn converts_angles() {
+        assert_close(Angle::from_degrees(180.).radians(), PI);
+        assert_close(Angle::from_degrees(-90.).rotations(), -0.25);
+        assert_close(Angle::from_rotations(1.5).degrees(), 540.);
+        assert_close(Angle::from_radians(PI / 2.).degrees(), 90.);
+    }
+
+    #[test]
+    fn normalizes_angles() {
+        for (degrees, expected) in [
+            (0., 0.),
+            (90., 90.),
+            (270., -90.),
+            (-270., 90.),
+            (720. + 45., 45.),
+            (-720. - 45., -45.),
+            (180., -180.),
+        ] {
+            let normalized = Angle::from_degrees(degrees).normalized();
+            assert_close(normalized.radians(), f32::to_radians(expected));
+        }
+    }
+
+    #[test]
+    fn converts_lengths() {
+        assert_close(Length::from_millimeters(250.).meters(), 0.25);
+        assert_close(
+            LinearVelocity::from_meters_per_second(1.5).millimeters_per_second(),
+            1500.,
+        );
+        // A wheel of 50 mm radius at one rotation per second.
+        let speed = AngularVelocity::from_rotations_per_second(1.) * Length::from_millimeters(50.);
+        assert_close(speed.meters_per_second(), 0.1 * PI);
+        let angle = AngularVelocity::from_rpm(30.) * Duration::from_secs(2);
+        assert_close(angle.rotations(), 1.);
+    }
+
+    #[test]
+    fn maps_axes_to_duty() {
+        assert_eq!(Duty::from_axis(127), Duty::MAX);
+        assert_eq!(Duty::from_axis(-128), Duty::MIN);
+        assert_eq!(Duty::from_axis(0), Duty::ZERO);
+        assert_close(Duty::from_axis(-127).value(), -1.);
+        assert_close(Duty::from_percent(-35.).value(), -0.35);
+    }
+
+    #[test]
+    fn keeps_duty_in_range() {
+        assert_eq!(Duty::new(1.5), Duty::MAX);
+        assert_eq!(Duty::MAX + Duty::MAX, Duty::MAX);
+        assert_eq!(Duty::MIN - Duty::MAX, Duty::MIN);
+        assert_eq!(Duty::new(0.8) * 2., Duty::MAX);
+        assert_eq!(Duty::new(-0.5) / 0.25, Duty::MIN);
+        let mut duty = Duty::new(0.7);
+        duty += Duty::new(0.7);
+        assert_eq!(duty, Duty::MAX);
+        duty -= Duty::new(2.);
+        assert_eq!(duty, Duty::ZERO);
+        // Other quantities aren't bounded.
+        assert_close((Angle::from_radians(3.) * 2.).radians(), 6.);
+    }
+}
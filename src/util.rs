@@ -1,4 +1,22 @@
+use core::convert::Infallible;
+
 pub fn sized_slice<const SIZE: usize>(slice: &[u8]) -> Option<&[u8; SIZE]> {
     let sized_slice = slice.try_into().ok()?;
     Some(sized_slice)
 }
+
+// Unwraps results whose error can never happen, e.g. from HALs with `Infallible`
+// pin and PWM errors.
+pub trait UnwrapInfallible<T> {
+    fn unwrap_infallible(self) -> T;
+}
+
+impl<T, E: Into<Infallible>> UnwrapInfallible<T> for Result<T, E> {
+    #[allow(unreachable_code)]
+    fn unwrap_infallible(self) -> T {
+        match self {
+            Ok(value) => value,
+            Err(e) => match e.into() {},
+        }
+    }
+}